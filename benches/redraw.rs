@@ -0,0 +1,53 @@
+// the time taken to redraw after the cursor moves, with and without the
+// rendered text cached, and to draw each frame of the cursor sliding.
+//
+// run with `cargo bench --bench redraw`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use grid_select::{config, fonts, grid, render};
+use std::hint::black_box;
+
+fn redraw(c: &mut Criterion) {
+    let config = config::Config::default();
+    let mut font_system = fonts::font_system(&config);
+
+    // the largest grid we'll draw
+    let choices: Vec<_> = (0..grid::max_items())
+        .map(|i| grid::Choice::new(format!("item number {i}")))
+        .collect();
+
+    let mut offscreen = render::Offscreen::new(&config, &choices, &mut font_system, 2).unwrap();
+    let (centre, right) = (grid::GridPosition::new(0, 0), grid::GridPosition::new(1, 0));
+    offscreen.draw(&centre);
+
+    let mut group = c.benchmark_group("redraw");
+
+    group.bench_function("cursor move", |b| {
+        b.iter(|| {
+            black_box(offscreen.move_cursor(&centre, &right));
+            black_box(offscreen.move_cursor(&right, &centre));
+        })
+    });
+
+    group.bench_function("cursor move, uncached", |b| {
+        b.iter(|| {
+            offscreen.clear_caches();
+            black_box(offscreen.move_cursor(&centre, &right));
+            offscreen.clear_caches();
+            black_box(offscreen.move_cursor(&right, &centre));
+        })
+    });
+
+    group.bench_function("cursor slide frame", |b| {
+        b.iter(|| black_box(offscreen.slide_cursor(&centre, &right, 0.5)).len())
+    });
+
+    group.bench_function("full redraw", |b| {
+        b.iter(|| black_box(offscreen.draw(&centre)).len())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, redraw);
+criterion_main!(benches);
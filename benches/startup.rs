@@ -0,0 +1,48 @@
+// time from starting up to having drawn the first frame, without a compositor.
+//
+// run with `cargo bench`. set GRID_SELECT_BENCH_FONT to the name of an installed
+// font to compare loading every system font against loading just that one.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use grid_select::{config, fonts, grid, render};
+
+fn choices() -> Vec<grid::Choice> {
+    [
+        "firefox", "kitty", "files", "settings", "editor", "music", "mail",
+    ]
+    .iter()
+    .map(|v| grid::Choice::new(v.to_string()))
+    .collect()
+}
+
+fn first_frame(config: &config::Config, choices: &[grid::Choice]) -> Vec<u32> {
+    let mut font_system = fonts::font_system(config);
+    let mut offscreen = render::Offscreen::new(config, choices, &mut font_system, 1).unwrap();
+    offscreen.draw(&grid::GridPosition::new(0, 0)).to_vec()
+}
+
+fn startup(c: &mut Criterion) {
+    let choices = choices();
+    let mut group = c.benchmark_group("startup to first frame");
+    group.sample_size(10);
+
+    let config = config::Config::default();
+    group.bench_function("system fonts", |b| {
+        b.iter(|| first_frame(&config, &choices))
+    });
+
+    if let Ok(font_name) = std::env::var("GRID_SELECT_BENCH_FONT") {
+        let config = config::Config {
+            font_name: Some(font_name),
+            ..Default::default()
+        };
+        // the first run fills the font cache
+        first_frame(&config, &choices);
+        group.bench_function("cached font", |b| b.iter(|| first_frame(&config, &choices)));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, startup);
+criterion_main!(benches);
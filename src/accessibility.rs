@@ -0,0 +1,168 @@
+// telling screen readers what's on the grid, with AccessKit over AT-SPI.
+//
+// the grid is presented as a list box, with an option for each item and the
+// item under the cursor focused. AccessKit only asks for the tree once a
+// screen reader is listening, so this costs nothing otherwise.
+
+use crate::state;
+use accesskit::{
+    Action, ActionHandler, ActionRequest, ActivationHandler, DeactivationHandler, Node, NodeId,
+    Role, Tree, TreeId, TreeUpdate,
+};
+use smithay_client_toolkit::reexports::calloop::channel;
+
+const WINDOW_ID: NodeId = NodeId(0);
+const LIST_ID: NodeId = NodeId(1);
+// the rest are items, in the order that the grid stores them
+const FIRST_ITEM_ID: u64 = 2;
+
+pub fn item_id(index: usize) -> NodeId {
+    NodeId(FIRST_ITEM_ID + index as u64)
+}
+
+// the grid item that a node stands for, if it's an item.
+pub fn item_index(node: NodeId) -> Option<usize> {
+    node.0
+        .checked_sub(FIRST_ITEM_ID)
+        .and_then(|i| i.try_into().ok())
+}
+
+// the whole tree, as it is now.
+pub fn tree(state: &state::State) -> TreeUpdate {
+    let items = &state.grid.items;
+
+    let mut nodes: Vec<(NodeId, Node)> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let mut node = Node::new(Role::ListBoxOption);
+            node.set_label(item.display.as_str());
+            if item.submenu.is_some() {
+                node.set_description("submenu");
+            } else if let Some(title) = items
+                .section_at(&item.position)
+                .and_then(|s| s.title.as_deref())
+            {
+                node.set_description(title);
+            }
+            node.set_position_in_set(i + 1);
+            node.set_size_of_set(items.len());
+            node.set_selected(item.position == state.cursor_position);
+            node.add_action(Action::Click);
+            node.add_action(Action::Focus);
+            (item_id(i), node)
+        })
+        .collect();
+
+    let mut list = Node::new(Role::ListBox);
+    list.set_children((0..items.len()).map(item_id).collect::<Vec<_>>());
+    list.set_size_of_set(items.len());
+    nodes.push((LIST_ID, list));
+
+    let mut window = Node::new(Role::Window);
+    window.set_label(crate::prog_name!());
+    window.set_children(vec![LIST_ID]);
+    nodes.push((WINDOW_ID, window));
+
+    let focus = items
+        .index_at(&state.cursor_position)
+        .map_or(LIST_ID, item_id);
+
+    TreeUpdate {
+        nodes,
+        tree: Some(Tree::new(WINDOW_ID)),
+        tree_id: TreeId::ROOT,
+        focus,
+    }
+}
+
+// what a screen reader wants, passed on to the event loop. AccessKit asks on
+// its own thread, but the state lives on ours.
+pub enum Request {
+    // send the whole tree
+    Activate,
+    // do something to an item
+    Action(ActionRequest),
+}
+
+struct Handler(channel::Sender<Request>);
+
+impl ActivationHandler for Handler {
+    fn request_initial_tree(&mut self) -> Option<TreeUpdate> {
+        // the tree will be sent from the event loop instead
+        let _ = self.0.send(Request::Activate);
+        None
+    }
+}
+
+impl ActionHandler for Handler {
+    fn do_action(&mut self, request: ActionRequest) {
+        let _ = self.0.send(Request::Action(request));
+    }
+}
+
+struct Deactivation;
+
+impl DeactivationHandler for Deactivation {
+    fn deactivate_accessibility(&mut self) {}
+}
+
+// start telling screen readers about the window. their requests arrive on
+// the returned channel.
+pub fn adapter() -> (accesskit_unix::Adapter, channel::Channel<Request>) {
+    let (sender, channel) = channel::channel();
+    let adapter =
+        accesskit_unix::Adapter::new(Handler(sender.clone()), Handler(sender), Deactivation);
+    (adapter, channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid;
+
+    fn node(update: &TreeUpdate, id: NodeId) -> &Node {
+        &update.nodes.iter().find(|(i, _)| *i == id).unwrap().1
+    }
+
+    #[test]
+    fn test_tree() {
+        let choices = vec![
+            grid::Choice {
+                display: Some("Firefox".to_string()),
+                ..grid::Choice::new("firefox".to_string())
+            },
+            grid::Choice {
+                children: vec![grid::Choice::new("b1".to_string())],
+                ..grid::Choice::new("b".to_string())
+            },
+        ];
+        let mut state = state::State::new(grid::Grid::new(&choices).unwrap(), None);
+
+        let update = tree(&state);
+        assert_eq!(4, update.nodes.len());
+        assert_eq!(Some(WINDOW_ID), update.tree.as_ref().map(|t| t.root));
+        assert_eq!(&[item_id(0), item_id(1)], node(&update, LIST_ID).children());
+
+        let first = node(&update, item_id(0));
+        assert_eq!(Role::ListBoxOption, first.role());
+        assert_eq!(Some("Firefox"), first.label());
+        assert_eq!(Some(1), first.position_in_set());
+        assert_eq!(Some(true), first.is_selected());
+        assert_eq!(item_id(0), update.focus);
+
+        // the focus follows the cursor
+        state.cursor_move_next();
+        let update = tree(&state);
+        assert_eq!(item_id(1), update.focus);
+        assert_eq!(Some(false), node(&update, item_id(0)).is_selected());
+        assert_eq!(Some("submenu"), node(&update, item_id(1)).description());
+    }
+
+    #[test]
+    fn test_item_index() {
+        assert_eq!(Some(3), item_index(item_id(3)));
+        assert_eq!(None, item_index(LIST_ID));
+        assert_eq!(None, item_index(WINDOW_ID));
+    }
+}
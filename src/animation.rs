@@ -0,0 +1,179 @@
+// transitions between the things that the window draws: the grid fading in
+// when it opens, the cursor sliding between items, and the chosen item
+// pulsing before we exit.
+//
+// each animation lasts `animation_duration_ms`. the window draws a frame of
+// each running animation whenever the compositor is ready for one.
+
+use crate::grid;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+pub struct Animation {
+    start: Instant,
+    duration: Duration,
+}
+
+impl Animation {
+    // how far through the animation we are at `now`, from 0 to 1.
+    pub fn progress(&self, now: Instant) -> f32 {
+        if self.duration.is_zero() {
+            return 1.;
+        }
+        let elapsed = now.saturating_duration_since(self.start);
+        (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.)
+    }
+
+    pub fn is_finished(&self, now: Instant) -> bool {
+        self.progress(now) >= 1.
+    }
+}
+
+// starts quickly and slows down towards the end.
+pub fn ease_out(t: f32) -> f32 {
+    1. - (1. - t).powi(3)
+}
+
+// the cursor moving from one item to another.
+pub struct Slide {
+    pub animation: Animation,
+    pub from: grid::GridPosition,
+    pub to: grid::GridPosition,
+}
+
+pub struct Animations {
+    duration: Duration,
+
+    pub opening: Option<Animation>,
+    pub slide: Option<Slide>,
+    pub pulse: Option<Animation>,
+}
+
+impl Animations {
+    // a duration of 0 disables animations.
+    pub fn new(duration_ms: u64) -> Animations {
+        Animations {
+            duration: Duration::from_millis(duration_ms),
+            opening: None,
+            slide: None,
+            pulse: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.duration.is_zero()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    fn start(&self, now: Instant) -> Option<Animation> {
+        self.enabled().then_some(Animation {
+            start: now,
+            duration: self.duration,
+        })
+    }
+
+    pub fn start_opening(&mut self, now: Instant) {
+        self.opening = self.start(now);
+    }
+
+    // returns the slide that was running, if any, so that it can be finished off.
+    pub fn start_slide(
+        &mut self,
+        from: grid::GridPosition,
+        to: grid::GridPosition,
+        now: Instant,
+    ) -> Option<Slide> {
+        let animation = self.start(now)?;
+        self.slide.replace(Slide {
+            animation,
+            from,
+            to,
+        })
+    }
+
+    pub fn start_pulse(&mut self, now: Instant) {
+        self.pulse = self.start(now);
+    }
+
+    // forget the animations that have been drawn to the end.
+    pub fn remove_finished(&mut self, now: Instant) {
+        if self.opening.is_some_and(|a| a.is_finished(now)) {
+            self.opening = None;
+        }
+        if self
+            .slide
+            .as_ref()
+            .is_some_and(|s| s.animation.is_finished(now))
+        {
+            self.slide = None;
+        }
+        if self.pulse.is_some_and(|a| a.is_finished(now)) {
+            self.pulse = None;
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.opening.is_some() || self.slide.is_some() || self.pulse.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let start = Instant::now();
+        let animation = Animation {
+            start,
+            duration: Duration::from_millis(100),
+        };
+
+        assert_eq!(0., animation.progress(start));
+        assert_eq!(0.5, animation.progress(start + Duration::from_millis(50)));
+        assert!(!animation.is_finished(start + Duration::from_millis(99)));
+        assert_eq!(1., animation.progress(start + Duration::from_secs(1)));
+        assert!(animation.is_finished(start + Duration::from_millis(100)));
+
+        assert_eq!(0., ease_out(0.));
+        assert_eq!(1., ease_out(1.));
+        assert!(ease_out(0.5) > 0.5);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut animations = Animations::new(0);
+        let now = Instant::now();
+
+        animations.start_opening(now);
+        animations.start_pulse(now);
+        let previous = animations.start_slide(
+            grid::GridPosition::new(0, 0),
+            grid::GridPosition::new(1, 0),
+            now,
+        );
+
+        assert!(previous.is_none());
+        assert!(!animations.running());
+    }
+
+    #[test]
+    fn test_remove_finished() {
+        let mut animations = Animations::new(100);
+        let now = Instant::now();
+
+        animations.start_opening(now);
+        animations.start_pulse(now + Duration::from_millis(50));
+        animations.remove_finished(now + Duration::from_millis(100));
+
+        assert!(animations.opening.is_none());
+        assert!(animations.pulse.is_some());
+        assert!(animations.running());
+
+        animations.remove_finished(now + Duration::from_millis(150));
+        assert!(!animations.running());
+    }
+}
@@ -0,0 +1,411 @@
+// copying the chosen value to the clipboard with wlr-data-control.
+//
+// a Wayland clipboard belongs to the client that set it: the compositor asks
+// us for the text each time it's pasted. we'd like to exit as soon as an item
+// is chosen, so the clipboard is served by a copy of ourselves that's left
+// running in the background until something else is copied.
+//
+// this uses its own event queue, like the window switcher, so that it doesn't
+// need a window.
+
+use anyhow::{Context, Result, bail};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{wl_registry, wl_seat};
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle, event_created_child};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::{self, ZwlrDataControlManagerV1},
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+// the types that plain text is asked for in, by Wayland and X11 clients.
+const MIME_TYPES: [&str; 5] = [
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "UTF8_STRING",
+    "STRING",
+    "TEXT",
+];
+
+// the option that runs us as the background server. it isn't in --help.
+const SERVE_OPTION: &str = "--serve-clipboard";
+
+struct State {
+    text: String,
+    cancelled: bool,
+    // pastes that are still being written
+    writers: Vec<JoinHandle<()>>,
+}
+
+pub struct Clipboard {
+    queue: EventQueue<State>,
+    state: State,
+}
+
+impl Clipboard {
+    // put the text on the clipboard of the compositor's first seat.
+    pub fn copy(conn: &Connection, text: &str) -> Result<Clipboard> {
+        let (globals, mut queue) = registry_queue_init::<State>(conn)?;
+        let qh = queue.handle();
+
+        let manager: ZwlrDataControlManagerV1 = globals
+            .bind(&qh, 1..=2, ())
+            .context("the compositor doesn't support wlr-data-control")?;
+        let seat: wl_seat::WlSeat = globals.bind(&qh, 1..=1, ()).context("no wl_seat")?;
+
+        let source = manager.create_data_source(&qh, ());
+        for mime_type in MIME_TYPES {
+            source.offer(mime_type.to_string());
+        }
+        let device = manager.get_data_device(&seat, &qh, ());
+        device.set_selection(Some(&source));
+
+        let mut state = State {
+            text: text.to_string(),
+            cancelled: false,
+            writers: Vec::new(),
+        };
+        queue.roundtrip(&mut state)?;
+
+        Ok(Clipboard { queue, state })
+    }
+
+    // hand out the text whenever it's pasted, until something else is copied.
+    // this waits for pastes that are still being written before it returns.
+    pub fn serve(mut self) -> Result<()> {
+        let served = self.serve_until_cancelled();
+        for writer in self.state.writers.drain(..) {
+            let _ = writer.join();
+        }
+        served
+    }
+
+    fn serve_until_cancelled(&mut self) -> Result<()> {
+        while !self.state.cancelled {
+            self.queue.blocking_dispatch(&mut self.state)?;
+            self.state.writers.retain(|w| !w.is_finished());
+        }
+        Ok(())
+    }
+}
+
+// copy the text, leaving a copy of ourselves in the background to serve it.
+// this returns once the text is on the clipboard.
+pub fn copy_in_background(text: &str) -> Result<()> {
+    let exe = std::env::current_exe().context("failed to find our own executable")?;
+    let mut child = Command::new(exe)
+        .arg(SERVE_OPTION)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // don't take the server down with our terminal
+        .process_group(0)
+        .spawn()
+        .context("failed to start the clipboard server")?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin.write_all(text.as_bytes())?;
+    drop(stdin);
+
+    // the server says "ok" once the text is on the clipboard, or why it isn't.
+    let mut reply = String::new();
+    BufReader::new(child.stdout.take().expect("stdout is piped")).read_line(&mut reply)?;
+    match reply.trim_end() {
+        "ok" => Ok(()),
+        "" => bail!("the clipboard server exited unexpectedly"),
+        error => bail!("{error}"),
+    }
+}
+
+// the background server started by `copy_in_background`: read the text from
+// stdin, copy it, and serve it until something else is copied.
+pub fn serve_from_stdin() -> Result<()> {
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+
+    let copied = Connection::connect_to_env()
+        .context("failed to connect to the compositor")
+        .and_then(|conn| Clipboard::copy(&conn, &text));
+
+    // there's no terminal to report errors to, so they go back to the picker.
+    let mut stdout = std::io::stdout();
+    match copied {
+        Ok(clipboard) => {
+            writeln!(stdout, "ok")?;
+            stdout.flush()?;
+            clipboard.serve()
+        }
+        Err(e) => {
+            writeln!(stdout, "{e:#}")?;
+            Ok(())
+        }
+    }
+}
+
+// write the text to a client that's pasting it. this is done on another
+// thread, so that a slow reader doesn't hold up anyone else.
+fn send(text: &str, fd: OwnedFd) -> JoinHandle<()> {
+    let text = text.to_string();
+    std::thread::spawn(move || {
+        if let Err(e) = std::fs::File::from(fd).write_all(text.as_bytes()) {
+            log::warn!("failed to paste: {e}");
+        }
+    })
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_seat::WlSeat,
+        _: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrDataControlManagerV1,
+        _: zwlr_data_control_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // the device is going away, so we can't keep the clipboard either.
+        if let zwlr_data_control_device_v1::Event::Finished = event {
+            state.cancelled = true;
+        }
+    }
+
+    event_created_child!(State, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ())
+    ]);
+}
+
+// we're told about what's on the clipboard (our own text, to begin with), but
+// we never read it.
+impl Dispatch<ZwlrDataControlOfferV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrDataControlOfferV1,
+        _: zwlr_data_control_offer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        source: &ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                log::debug!("pasting as {mime_type}");
+                state.writers.push(send(&state.text, fd));
+            }
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                source.destroy();
+                state.cancelled = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_compositor::TestCompositor;
+    use std::os::fd::AsFd;
+    use wayland_protocols_wlr::data_control::v1::server::{
+        zwlr_data_control_device_v1 as server_device,
+        zwlr_data_control_manager_v1 as server_manager,
+        zwlr_data_control_source_v1 as server_source,
+    };
+    use wayland_server::protocol::wl_seat as server_seat;
+    use wayland_server::{
+        Client, DataInit, Dispatch as ServerDispatch, DisplayHandle, GlobalDispatch, New,
+    };
+
+    // a compositor that pastes whatever's copied straight away, and then
+    // replaces it.
+    #[derive(Default)]
+    struct Compositor {
+        offered: Vec<String>,
+        pasted: Option<std::io::PipeReader>,
+    }
+
+    impl GlobalDispatch<server_manager::ZwlrDataControlManagerV1, ()> for Compositor {
+        fn bind(
+            _: &mut Self,
+            _: &DisplayHandle,
+            _: &Client,
+            resource: New<server_manager::ZwlrDataControlManagerV1>,
+            _: &(),
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            data_init.init(resource, ());
+        }
+    }
+
+    impl ServerDispatch<server_manager::ZwlrDataControlManagerV1, ()> for Compositor {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            _: &server_manager::ZwlrDataControlManagerV1,
+            request: server_manager::Request,
+            _: &(),
+            _: &DisplayHandle,
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            match request {
+                server_manager::Request::CreateDataSource { id } => {
+                    data_init.init(id, ());
+                }
+                server_manager::Request::GetDataDevice { id, .. } => {
+                    data_init.init(id, ());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    impl ServerDispatch<server_source::ZwlrDataControlSourceV1, ()> for Compositor {
+        fn request(
+            state: &mut Self,
+            _: &Client,
+            _: &server_source::ZwlrDataControlSourceV1,
+            request: server_source::Request,
+            _: &(),
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+            if let server_source::Request::Offer { mime_type } = request {
+                state.offered.push(mime_type);
+            }
+        }
+    }
+
+    impl ServerDispatch<server_device::ZwlrDataControlDeviceV1, ()> for Compositor {
+        fn request(
+            state: &mut Self,
+            _: &Client,
+            _: &server_device::ZwlrDataControlDeviceV1,
+            request: server_device::Request,
+            _: &(),
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+            if let server_device::Request::SetSelection {
+                source: Some(source),
+            } = request
+            {
+                let (reader, writer) = std::io::pipe().unwrap();
+                source.send(MIME_TYPES[0].to_string(), writer.as_fd());
+                source.cancelled();
+                state.pasted = Some(reader);
+            }
+        }
+    }
+
+    impl GlobalDispatch<server_seat::WlSeat, ()> for Compositor {
+        fn bind(
+            _: &mut Self,
+            _: &DisplayHandle,
+            _: &Client,
+            resource: New<server_seat::WlSeat>,
+            _: &(),
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            data_init.init(resource, ());
+        }
+    }
+
+    impl ServerDispatch<server_seat::WlSeat, ()> for Compositor {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            _: &server_seat::WlSeat,
+            _: server_seat::Request,
+            _: &(),
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+        }
+    }
+
+    #[test]
+    fn test_copy_and_paste() {
+        let (conn, server) = TestCompositor::spawn(Compositor::default(), |dh| {
+            dh.create_global::<Compositor, server_manager::ZwlrDataControlManagerV1, ()>(2, ());
+            dh.create_global::<Compositor, server_seat::WlSeat, ()>(1, ());
+        });
+
+        // serving returns once the compositor has replaced our text
+        Clipboard::copy(&conn, "chosen value ✓")
+            .unwrap()
+            .serve()
+            .unwrap();
+
+        let compositor = server.stop();
+        assert_eq!(MIME_TYPES.to_vec(), compositor.offered);
+
+        let mut pasted = String::new();
+        compositor
+            .pasted
+            .unwrap()
+            .read_to_string(&mut pasted)
+            .unwrap();
+        assert_eq!("chosen value ✓", pasted);
+    }
+
+    #[test]
+    fn test_unsupported_compositor() {
+        let (conn, server) = TestCompositor::spawn(Compositor::default(), |dh| {
+            dh.create_global::<Compositor, server_seat::WlSeat, ()>(1, ());
+        });
+
+        assert!(Clipboard::copy(&conn, "value").is_err());
+        server.stop();
+    }
+}
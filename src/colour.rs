@@ -2,7 +2,7 @@ use anyhow::Context;
 use raqote::SolidSource;
 use serde::Deserialize;
 
-#[derive(Deserialize, Clone, PartialEq)]
+#[derive(Deserialize, Clone, PartialEq, Eq, Hash)]
 #[cfg_attr(test, derive(Debug))]
 #[serde(try_from = "String")]
 pub struct Colour(u32);
@@ -10,6 +10,9 @@ pub struct Colour(u32);
 // much of this implementation is borrowed from yofi under the MIT license
 // copyright 2018 kitsu
 impl Colour {
+    pub const BLACK: Colour = Colour::from_rgba(0x00, 0x00, 0x00, 0xff);
+    pub const WHITE: Colour = Colour::from_rgba(0xff, 0xff, 0xff, 0xff);
+
     pub const fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
         Self(u32::from_be_bytes([r, g, b, a]))
     }
@@ -27,6 +30,92 @@ impl Colour {
         let [r, g, b, a] = self.to_rgba();
         cosmic_text::Color::rgba(r, g, b, a)
     }
+
+    // build an opaque colour from hue (degrees), saturation and lightness (0..=1).
+    pub fn from_hsl(h: f32, s: f32, l: f32) -> Self {
+        let h = h.rem_euclid(360.);
+        let s = s.clamp(0., 1.);
+        let l = l.clamp(0., 1.);
+
+        let c = (1. - (2. * l - 1.).abs()) * s;
+        let x = c * (1. - ((h / 60.) % 2. - 1.).abs());
+        let m = l - c / 2.;
+
+        let (r, g, b) = match h as u32 / 60 {
+            0 => (c, x, 0.),
+            1 => (x, c, 0.),
+            2 => (0., c, x),
+            3 => (0., x, c),
+            4 => (x, 0., c),
+            _ => (c, 0., x),
+        };
+
+        let to_u8 = |v: f32| ((v + m) * 255.).round() as u8;
+        Self::from_rgba(to_u8(r), to_u8(g), to_u8(b), 0xff)
+    }
+
+    // the hue (degrees), saturation and lightness (0..=1) of this colour.
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let [r, g, b, _] = self.to_rgba();
+        let (r, g, b) = (r as f32 / 255., g as f32 / 255., b as f32 / 255.);
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let l = (max + min) / 2.;
+        if delta == 0. {
+            return (0., 0., l);
+        }
+
+        let s = delta / (1. - (2. * l - 1.).abs());
+        let h = if max == r {
+            60. * ((g - b) / delta).rem_euclid(6.)
+        } else if max == g {
+            60. * ((b - r) / delta + 2.)
+        } else {
+            60. * ((r - g) / delta + 4.)
+        };
+
+        (h, s, l)
+    }
+
+    // relative luminance as defined by WCAG 2.
+    // https://www.w3.org/TR/WCAG21/#dfn-relative-luminance
+    pub fn relative_luminance(&self) -> f32 {
+        let linearise = |c: u8| {
+            let c = c as f32 / 255.;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        let [r, g, b, _] = self.to_rgba();
+        0.2126 * linearise(r) + 0.7152 * linearise(g) + 0.0722 * linearise(b)
+    }
+
+    // the WCAG 2 contrast ratio between two colours, from 1 (none) to 21 (black on white).
+    // https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+    pub fn contrast_ratio(&self, other: &Colour) -> f32 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    // whichever of the candidates has the highest contrast against this colour.
+    pub fn most_contrasting<'a>(&self, candidates: &[&'a Colour]) -> &'a Colour {
+        candidates
+            .iter()
+            .max_by(|a, b| self.contrast_ratio(a).total_cmp(&self.contrast_ratio(b)))
+            .expect("no candidate colours")
+    }
+
+    // black or white, whichever is easier to read on top of this colour.
+    pub fn readable_foreground(&self) -> Colour {
+        self.most_contrasting(&[&Colour::BLACK, &Colour::WHITE])
+            .clone()
+    }
 }
 
 impl TryFrom<String> for Colour {
@@ -41,18 +130,268 @@ impl TryFrom<String> for Colour {
     }
 }
 
+// a text colour, either fixed or picked per item to contrast with its background.
+#[derive(Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(from = "ForegroundConfig")]
+pub enum Foreground {
+    Fixed(Colour),
+    Auto { light: Colour, dark: Colour },
+}
+
+impl Foreground {
+    pub fn resolve(&self, bg: &Colour) -> Colour {
+        match self {
+            Foreground::Fixed(c) => c.clone(),
+            Foreground::Auto { light, dark } => bg.most_contrasting(&[light, dark]).clone(),
+        }
+    }
+}
+
+// fg_colour = "RRGGBB", fg_colour = "auto" (black or white),
+// or fg_colour = { mode = "auto", light = "RRGGBB", dark = "RRGGBB" }
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ForegroundConfig {
+    Fixed(Colour),
+    Auto(AutoKeyword),
+    AutoBetween {
+        #[allow(dead_code)]
+        mode: AutoKeyword,
+        light: Colour,
+        dark: Colour,
+    },
+}
+
+#[derive(Deserialize)]
+enum AutoKeyword {
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+impl From<ForegroundConfig> for Foreground {
+    fn from(value: ForegroundConfig) -> Self {
+        match value {
+            ForegroundConfig::Fixed(c) => Foreground::Fixed(c),
+            ForegroundConfig::Auto(_) => Foreground::Auto {
+                light: Colour::WHITE,
+                dark: Colour::BLACK,
+            },
+            ForegroundConfig::AutoBetween { light, dark, .. } => Foreground::Auto { light, dark },
+        }
+    }
+}
+
 #[derive(Deserialize, Clone)]
 #[serde(untagged)]
 pub enum Colours {
     One(Colour),
-    Many(Vec<Colour>),
+    Many(#[serde(deserialize_with = "non_empty")] Vec<Colour>),
+    Generated(Generator),
+}
+
+// items take turns at the listed colours, so there has to be at least one.
+fn non_empty<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<Colour>, D::Error> {
+    let colours = Vec::<Colour>::deserialize(deserializer)?;
+    if colours.is_empty() {
+        return Err(serde::de::Error::custom("expected at least one colour"));
+    }
+    Ok(colours)
+}
+
+// colours that are computed from each item rather than listed in the config.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum Generator {
+    Hash(HashRange),
+}
+
+// like XMonad's colorRangeFromClassName: each item's value is hashed to pick a
+// colour between `from` and `to`, so the same item always gets the same colour.
+//
+// the hue travels from `from` to `to` in increasing hue order. saturation and
+// lightness are interpolated along with the hue unless they're given explicitly.
+#[derive(Deserialize, Clone)]
+pub struct HashRange {
+    pub from: Colour,
+    pub to: Colour,
+    pub saturation: Option<f32>,
+    pub lightness: Option<f32>,
+}
+
+impl HashRange {
+    pub fn colour_for(&self, key: &str) -> Colour {
+        let t = (fnv1a(key.as_bytes()) % 1024) as f32 / 1023.;
+
+        let (from_h, from_s, from_l) = self.from.to_hsl();
+        let (to_h, to_s, to_l) = self.to.to_hsl();
+
+        let hue_span = (to_h - from_h).rem_euclid(360.);
+        let h = from_h + hue_span * t;
+        let s = self.saturation.unwrap_or(from_s + (to_s - from_s) * t);
+        let l = self.lightness.unwrap_or(from_l + (to_l - from_l) * t);
+
+        Colour::from_hsl(h, s, l)
+    }
+}
+
+// a small hash that's stable across builds and platforms, unlike
+// std's DefaultHasher. items need to keep their colour between runs.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    })
 }
 
 impl Colours {
-    pub fn cycle(&self) -> Box<dyn Iterator<Item = &Colour> + '_> {
+    // the background colour for the item at `index` in spiral order, with value `key`.
+    pub fn colour_for(&self, index: usize, key: &str) -> Colour {
         match self {
-            Colours::One(c) => Box::new(std::iter::repeat(c)),
-            Colours::Many(cs) => Box::new(cs.iter().cycle()),
+            Colours::One(c) => c.clone(),
+            Colours::Many(cs) => cs[index % cs.len()].clone(),
+            Colours::Generated(Generator::Hash(range)) => range.colour_for(key),
         }
     }
+
+    // generated colours aren't known ahead of time, so a single configured
+    // foreground colour can't be relied on to be readable.
+    pub fn is_generated(&self) -> bool {
+        matches!(self, Colours::Generated(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hsl_round_trip() {
+        for c in [
+            Colour::from_rgba(0x33, 0x66, 0x99, 0xff),
+            Colour::from_rgba(0xfa, 0xbd, 0x2f, 0xff),
+            Colour::BLACK,
+            Colour::WHITE,
+        ] {
+            let (h, s, l) = c.to_hsl();
+            assert_eq!(c, Colour::from_hsl(h, s, l));
+        }
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        let range = HashRange {
+            from: Colour::from_rgba(0xff, 0x00, 0x00, 0xff),
+            to: Colour::from_rgba(0x00, 0x00, 0xff, 0xff),
+            saturation: Some(0.5),
+            lightness: Some(0.5),
+        };
+
+        assert_eq!(range.colour_for("firefox"), range.colour_for("firefox"));
+        assert_ne!(range.colour_for("firefox"), range.colour_for("kitty"));
+
+        // changing the hash would change every user's colours.
+        assert_eq!(fnv1a(b"firefox"), 0xb8f122c65fd1567a);
+    }
+
+    #[test]
+    fn test_readable_foreground() {
+        assert_eq!(Colour::WHITE, Colour::BLACK.readable_foreground());
+        assert_eq!(Colour::BLACK, Colour::WHITE.readable_foreground());
+        assert_eq!(
+            Colour::BLACK,
+            Colour::from_rgba(0xfa, 0xbd, 0x2f, 0xff).readable_foreground()
+        );
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        let grey = Colour::from_rgba(0x77, 0x77, 0x77, 0xff);
+        let close = |a: f32, b: f32| (a - b).abs() < 0.01;
+
+        assert!(close(21., Colour::BLACK.contrast_ratio(&Colour::WHITE)));
+        assert!(close(21., Colour::WHITE.contrast_ratio(&Colour::BLACK)));
+        assert!(close(1., grey.contrast_ratio(&grey)));
+        assert!(close(4.48, grey.contrast_ratio(&Colour::WHITE)));
+    }
+
+    #[test]
+    fn test_auto_foreground() {
+        let light = Colour::from_rgba(0xeb, 0xdb, 0xb2, 0xff);
+        let dark = Colour::from_rgba(0x28, 0x28, 0x28, 0xff);
+        let auto = Foreground::Auto {
+            light: light.clone(),
+            dark: dark.clone(),
+        };
+
+        assert_eq!(
+            light,
+            auto.resolve(&Colour::from_rgba(0x3c, 0x38, 0x36, 0xff))
+        );
+        assert_eq!(
+            dark,
+            auto.resolve(&Colour::from_rgba(0xfa, 0xbd, 0x2f, 0xff))
+        );
+
+        let fixed = Foreground::Fixed(light.clone());
+        assert_eq!(light, fixed.resolve(&Colour::WHITE));
+    }
+
+    #[test]
+    fn test_deserialize_foreground() {
+        #[derive(Deserialize)]
+        struct C {
+            fg_colour: Foreground,
+        }
+
+        let parse = |s| toml::from_str::<C>(s).unwrap().fg_colour;
+
+        assert_eq!(
+            Foreground::Fixed(Colour::from_rgba(0x00, 0xcc, 0x00, 0xff)),
+            parse(r#"fg_colour = "00cc00""#)
+        );
+        assert_eq!(
+            Foreground::Auto {
+                light: Colour::WHITE,
+                dark: Colour::BLACK
+            },
+            parse(r#"fg_colour = "auto""#)
+        );
+        assert_eq!(
+            Foreground::Auto {
+                light: Colour::from_rgba(0xee, 0xee, 0xee, 0xff),
+                dark: Colour::from_rgba(0x11, 0x11, 0x11, 0xff),
+            },
+            parse(r#"fg_colour = { mode = "auto", light = "eeeeee", dark = "111111" }"#)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_hash() {
+        #[derive(Deserialize)]
+        struct C {
+            bg_colour: Colours,
+        }
+
+        let c: C = toml::from_str(
+            r#"bg_colour = { mode = "hash", from = "ff0000", to = "0000ff", lightness = 0.4 }"#,
+        )
+        .unwrap();
+        assert!(c.bg_colour.is_generated());
+    }
+
+    #[test]
+    fn test_deserialize_list() {
+        #[derive(Deserialize)]
+        struct C {
+            bg_colour: Colours,
+        }
+
+        let c: C = toml::from_str(r#"bg_colour = ["336699", "996633"]"#).unwrap();
+        assert_eq!(
+            Colour::from_rgba(0x99, 0x66, 0x33, 0xff),
+            c.bg_colour.colour_for(3, "a")
+        );
+
+        assert!(toml::from_str::<C>("bg_colour = []").is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::colour;
+use crate::{colour, icon, text, theme};
 use anyhow::{Context, Result};
 use defaults::Defaults;
 use serde::Deserialize;
@@ -27,16 +27,16 @@ mod config_defaults {
         colour::Colours::One(colour::Colour::from_rgba(0x3c, 0x38, 0x36, 0xff))
     }
 
-    pub fn fg_colour() -> colour::Colour {
-        colour::Colour::from_rgba(0xa8, 0x99, 0x84, 0xff)
+    pub fn fg_colour() -> colour::Foreground {
+        colour::Foreground::Fixed(colour::Colour::from_rgba(0xa8, 0x99, 0x84, 0xff))
     }
 
     pub fn active_bg_colour() -> colour::Colour {
         colour::Colour::from_rgba(0xfa, 0xbd, 0x2f, 0xff)
     }
 
-    pub fn active_fg_colour() -> colour::Colour {
-        colour::Colour::from_rgba(0x28, 0x28, 0x28, 0xff)
+    pub fn active_fg_colour() -> colour::Foreground {
+        colour::Foreground::Fixed(colour::Colour::from_rgba(0x28, 0x28, 0x28, 0xff))
     }
 
     pub fn border_colour() -> colour::Colour {
@@ -44,6 +44,10 @@ mod config_defaults {
     }
 }
 
+// the borders that high_contrast makes items have, at least.
+const HIGH_CONTRAST_BORDER_WIDTH: f32 = 3.;
+const HIGH_CONTRAST_ACTIVE_BORDER_WIDTH: f32 = 6.;
+
 #[derive(Defaults, Deserialize)]
 #[serde(default)]
 pub struct Config {
@@ -57,44 +61,182 @@ pub struct Config {
     #[def = "1."]
     pub border_width: f32,
 
+    // the border of the selected item. defaults to border_width.
+    pub active_border_width: Option<f32>,
+
+    // the radius of each item's corners.
+    #[def = "0."]
+    pub corner_radius: f32,
+
+    // the space between the edge of each item and its text.
+    #[def = "0."]
+    pub item_padding: f32,
+
     #[def = "config_defaults::bg_colour()"]
     pub bg_colour: colour::Colours,
 
     #[def = "config_defaults::fg_colour()"]
-    pub fg_colour: colour::Colour,
+    pub fg_colour: colour::Foreground,
 
     #[def = "config_defaults::active_bg_colour()"]
     pub active_bg_colour: colour::Colour,
 
     #[def = "config_defaults::active_fg_colour()"]
-    pub active_fg_colour: colour::Colour,
+    pub active_fg_colour: colour::Foreground,
 
     #[def = "config_defaults::border_colour()"]
     pub border_colour: colour::Colour,
 
+    // the border colour of the selected item. defaults to border_colour.
+    pub active_border_colour: Option<colour::Colour>,
+
     #[def = "18."]
     pub font_size: f32,
     pub font_name: Option<String>,
+
+    // font files and directories to load, instead of every system font.
+    pub fonts: Vec<PathBuf>,
+
+    // how to handle text that doesn't fit in its item.
+    pub text_overflow: text::TextOverflow,
+
+    // the most lines that wrapped text can take up.
+    pub text_max_lines: Option<usize>,
+
+    // the smallest size that "shrink" will reduce the font to.
+    #[def = "8."]
+    pub min_font_size: f32,
+
+    // where each item's icon is drawn relative to its text.
+    pub icon_position: icon::IconPosition,
+
+    // the width and height of icons.
+    #[def = "24."]
+    pub icon_size: f32,
+
+    // the freedesktop icon theme that icon names are looked up in.
+    pub icon_theme: Option<String>,
+
+    // does moving the cursor off the edge of the grid wrap around to the other side?
+    pub wrap_cursor: bool,
+
+    // how long transitions (opening, moving the cursor and choosing an item)
+    // take, in milliseconds. 0 turns them off.
+    pub animation_duration_ms: u64,
+
+    // replace the colours with white on black, and thicken the borders,
+    // whatever the theme or the rest of the config says.
+    pub high_contrast: bool,
 }
 
 impl Config {
-    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+    // load the config file, layered on top of a theme.
+    //
+    // the theme is `theme_override` if it's given, otherwise the config file's `theme` key.
+    // keys set in the config file take precedence over the theme's.
+    pub fn load(path: Option<PathBuf>, theme_override: Option<&str>) -> Result<Self> {
         let path = match path {
-            Some(p) => p,
-            None => match default_config_path()? {
-                Some(path) => path,
-                None => return Ok(Config::default()),
+            Some(p) => Some(p),
+            None => default_config_path()?,
+        };
+        let table = match path {
+            None => toml::Table::new(),
+            Some(path) => match std::fs::read_to_string(&path) {
+                Ok(c) => toml::from_str(&c).context("invalid config")?,
+                Err(err) if matches!(err.kind(), std::io::ErrorKind::NotFound) => {
+                    toml::Table::new()
+                }
+                Err(e) => {
+                    return Err(
+                        anyhow::Error::new(e).context(format!("config read at {}", path.display()))
+                    );
+                }
             },
         };
-        match std::fs::read_to_string(&path) {
-            Ok(c) => toml::from_str(&c).context("invalid config"),
-            Err(err) if matches!(err.kind(), std::io::ErrorKind::NotFound) => Ok(Config::default()),
-            Err(e) => {
-                Err(anyhow::Error::new(e).context(format!("config read at {}", path.display())))
-            }
+
+        Self::from_table(table, theme_override, theme::load)
+    }
+
+    // `load_theme` turns a theme's name into its table of config keys.
+    fn from_table(
+        mut table: toml::Table,
+        theme_override: Option<&str>,
+        load_theme: impl Fn(&str) -> Result<toml::Table>,
+    ) -> Result<Self> {
+        let theme_name = match (theme_override, table.remove("theme")) {
+            (Some(name), _) => Some(name.to_string()),
+            (None, Some(toml::Value::String(name))) => Some(name),
+            (None, Some(_)) => anyhow::bail!("invalid config: theme must be a string"),
+            (None, None) => None,
+        };
+
+        // only a text colour that the user chose is kept on generated
+        // backgrounds (see below). a theme's is meant for its own backgrounds.
+        let fg_colour_set = table.contains_key("fg_colour");
+
+        if let Some(name) = theme_name {
+            let mut themed = load_theme(&name)?;
+            themed.extend(table);
+            table = themed;
+        }
+
+        let mut config: Config = table.try_into().context("invalid config")?;
+        // the default text colour can't be relied on to be readable on
+        // generated backgrounds, but one that's been chosen is kept.
+        if config.bg_colour.is_generated() && !fg_colour_set {
+            config.fg_colour = colour::Foreground::Auto {
+                light: colour::Colour::WHITE,
+                dark: colour::Colour::BLACK,
+            };
+        }
+        if config.high_contrast {
+            config.apply_high_contrast();
+        }
+        Ok(config)
+    }
+
+    // white text on black items, with the selected item inverted and outlined
+    // in yellow so that it doesn't depend on colour alone.
+    fn apply_high_contrast(&mut self) {
+        let black = colour::Colour::from_rgba(0x00, 0x00, 0x00, 0xff);
+        let white = colour::Colour::from_rgba(0xff, 0xff, 0xff, 0xff);
+        let yellow = colour::Colour::from_rgba(0xff, 0xff, 0x00, 0xff);
+
+        self.bg_colour = colour::Colours::One(black.clone());
+        self.fg_colour = colour::Foreground::Fixed(white.clone());
+        self.active_bg_colour = white.clone();
+        self.active_fg_colour = colour::Foreground::Fixed(black);
+        self.border_colour = white;
+        self.active_border_colour = Some(yellow);
+
+        self.border_width = self.border_width.max(HIGH_CONTRAST_BORDER_WIDTH);
+        self.active_border_width = Some(
+            self.active_border_width
+                .unwrap_or(self.border_width)
+                .max(HIGH_CONTRAST_ACTIVE_BORDER_WIDTH),
+        );
+    }
+
+    // the colour and width of an item's border.
+    pub fn border_style(&self, is_selected: bool) -> (&colour::Colour, f32) {
+        if is_selected {
+            (
+                self.active_border_colour
+                    .as_ref()
+                    .unwrap_or(&self.border_colour),
+                self.active_border_width.unwrap_or(self.border_width),
+            )
+        } else {
+            (&self.border_colour, self.border_width)
         }
     }
 
+    // the widest border that any item can have.
+    pub fn max_border_width(&self) -> f32 {
+        self.border_width
+            .max(self.active_border_width.unwrap_or(self.border_width))
+    }
+
     pub fn param<'a, T>(&'a self) -> T
     where
         T: From<&'a Self>,
@@ -102,3 +244,107 @@ impl Config {
         self.into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_overrides_theme() {
+        let table = toml::from_str(
+            r#"
+            theme = "nord"
+            active_bg_colour = "ff0000"
+            "#,
+        )
+        .unwrap();
+        let config = Config::from_table(table, None, theme::load_builtin).unwrap();
+
+        // from the config
+        assert_eq!(
+            colour::Colour::from_rgba(0xff, 0x00, 0x00, 0xff),
+            config.active_bg_colour
+        );
+        // from the theme
+        assert_eq!(
+            colour::Colour::from_rgba(0x4c, 0x56, 0x6a, 0xff),
+            config.border_colour
+        );
+    }
+
+    #[test]
+    fn test_theme_override() {
+        let table = toml::from_str(r#"theme = "nord""#).unwrap();
+        let config = Config::from_table(table, Some("gruvbox-light"), theme::load_builtin).unwrap();
+
+        assert_eq!(
+            colour::Colour::from_rgba(0x3c, 0x38, 0x36, 0xff),
+            config.border_colour
+        );
+    }
+
+    #[test]
+    fn test_high_contrast() {
+        let table = toml::from_str(
+            r#"
+            theme = "nord"
+            high_contrast = true
+            fg_colour = "808080"
+            active_border_width = 10.0
+            "#,
+        )
+        .unwrap();
+        let config = Config::from_table(table, None, theme::load_builtin).unwrap();
+
+        // the most contrast there is, is 21:1
+        let bg = config.bg_colour.colour_for(0, "a");
+        assert!(bg.contrast_ratio(&config.fg_colour.resolve(&bg)) > 20.9);
+        let active_bg = &config.active_bg_colour;
+        assert!(active_bg.contrast_ratio(&config.active_fg_colour.resolve(active_bg)) > 20.9);
+        assert_eq!(
+            colour::Colour::from_rgba(0xff, 0xff, 0xff, 0xff),
+            config.border_colour
+        );
+
+        // borders are thickened, but not thinned
+        assert_eq!(
+            (HIGH_CONTRAST_BORDER_WIDTH, 10.),
+            (config.border_width, config.active_border_width.unwrap())
+        );
+    }
+
+    #[test]
+    fn test_generated_backgrounds() {
+        let hashed = r#"bg_colour = { mode = "hash", from = "cc241d", to = "458588" }"#;
+
+        // text is black or white by default
+        let table = toml::from_str(hashed).unwrap();
+        let config = Config::from_table(table, None, theme::load_builtin).unwrap();
+        assert!(matches!(config.fg_colour, colour::Foreground::Auto { .. }));
+
+        // but a colour that's been chosen is used as it is
+        let table = toml::from_str(&format!("{hashed}\nfg_colour = \"808080\"")).unwrap();
+        let config = Config::from_table(table, None, theme::load_builtin).unwrap();
+        assert_eq!(
+            colour::Foreground::Fixed(colour::Colour::from_rgba(0x80, 0x80, 0x80, 0xff)),
+            config.fg_colour
+        );
+
+        // a theme's text colour doesn't count as chosen, whichever way the
+        // theme is picked
+        let table = toml::from_str(&format!("{hashed}\ntheme = \"nord\"")).unwrap();
+        let config = Config::from_table(table, None, theme::load_builtin).unwrap();
+        assert!(matches!(config.fg_colour, colour::Foreground::Auto { .. }));
+
+        let table = toml::from_str(hashed).unwrap();
+        let config = Config::from_table(table, Some("nord"), theme::load_builtin).unwrap();
+        assert!(matches!(config.fg_colour, colour::Foreground::Auto { .. }));
+    }
+
+    #[test]
+    fn test_unknown_theme() {
+        assert!(
+            Config::from_table(toml::Table::new(), Some("no-such-theme"), theme::load).is_err()
+        );
+    }
+}
@@ -0,0 +1,323 @@
+// a long-running process that shows pickers on behalf of clients, so that
+// each picker doesn't have to pay for loading fonts and connecting to the
+// compositor.
+//
+// clients connect to $XDG_RUNTIME_DIR/grid-select.sock, send a single Request,
+// and wait for a single Response. each message is a 4 byte big-endian length
+// followed by that many bytes of JSON.
+//
+// only one picker is shown at a time. a client that connects while one is open
+// is told that the daemon is busy.
+
+use crate::{config, grid, input, state, window};
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+// requests are bounded so that a bad client can't make us allocate arbitrarily.
+const MAX_MESSAGE_LEN: u32 = 16 * 1024 * 1024;
+// requests are read while we're marked as busy, so a client that never
+// finishes sending one mustn't hold us up for long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Request {
+    pub choices: Vec<grid::Choice>,
+    // the value of the item that the cursor should start on
+    pub initial_value: Option<String>,
+    // how to read the choices that submenu commands print
+    #[serde(default)]
+    pub format: input::Format,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum Response {
+    // the item that was chosen, or None if the picker was dismissed
+    Selected(Option<state::Selection>),
+    Error(String),
+}
+
+pub fn socket_path() -> Result<PathBuf> {
+    let runtime_dir = xdg::BaseDirectories::new()
+        .get_runtime_directory()
+        .context("$XDG_RUNTIME_DIR isn't set")?
+        .clone();
+    Ok(runtime_dir.join(concat!(crate::prog_name!(), ".sock")))
+}
+
+fn write_message<T: Serialize>(w: &mut impl Write, message: &T) -> Result<()> {
+    let data = serde_json::to_vec(message)?;
+    let len = u32::try_from(data.len())
+        .ok()
+        .filter(|len| *len <= MAX_MESSAGE_LEN)
+        .context("message is too large")?;
+
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(&data)?;
+    w.flush()?;
+    Ok(())
+}
+
+fn read_message<T: DeserializeOwned>(r: &mut impl Read) -> Result<T> {
+    let mut len = [0; 4];
+    r.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len);
+    anyhow::ensure!(len <= MAX_MESSAGE_LEN, "message is too large: {len} bytes");
+
+    let mut data = vec![0; len as usize];
+    r.read_exact(&mut data)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+// ask the daemon to show a picker, returning the chosen item.
+pub fn request(request: &Request) -> Result<Option<state::Selection>> {
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path).with_context(|| {
+        format!(
+            "couldn't connect to the daemon at {} (is `{} --daemon` running?)",
+            path.display(),
+            crate::prog_name!()
+        )
+    })?;
+
+    write_message(&mut stream, request)?;
+    match read_message(&mut stream)? {
+        Response::Selected(value) => Ok(value),
+        Response::Error(e) => anyhow::bail!("the daemon failed: {e}"),
+    }
+}
+
+// listen on the socket, replacing a stale socket that was left behind by a
+// daemon that didn't shut down cleanly.
+fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        anyhow::ensure!(
+            UnixStream::connect(path).is_err(),
+            "a daemon is already listening on {}",
+            path.display()
+        );
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+
+    UnixListener::bind(path).with_context(|| format!("failed to listen on {}", path.display()))
+}
+
+// show a picker for one request.
+//
+// the context is taken so that it can be lent to the window, and is put back
+// afterwards, even if the picker fails. if it's not put back, the connection to
+// the compositor is gone.
+fn show(
+    context: &mut Option<window::Context>,
+    config: config::Config,
+    request: &Request,
+) -> Result<Option<state::Selection>> {
+    // a bad request is turned away before there's a window to clean up
+    let grid = grid::Grid::new(&request.choices)?;
+
+    let (mut window, mut event_loop) = window::Window::new(
+        context.take().context("no connection to the compositor")?,
+        config,
+        grid,
+        request.initial_value.as_deref(),
+    );
+    window.set_format(request.format.clone());
+
+    let mut dispatched = Ok(());
+    while dispatched.is_ok() && !window.should_exit() {
+        dispatched = event_loop.dispatch(None, &mut window);
+    }
+
+    let selected = window.selected().cloned();
+    *context = Some(window.into_context(event_loop));
+
+    dispatched?;
+    Ok(selected)
+}
+
+// run the daemon until it's killed.
+//
+// `load_config` is called for each picker, so that config changes take effect
+// without restarting the daemon.
+pub fn serve(load_config: impl Fn() -> Result<config::Config>) -> Result<()> {
+    let path = socket_path()?;
+    let listener = bind(&path)?;
+    // fonts are loaded once, so changes to the font config need a restart.
+    let mut context = Some(window::Context::new(&load_config()?)?);
+
+    // connections are accepted on another thread, so that clients can be told
+    // that we're busy while a picker is open.
+    let busy = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::spawn({
+        let busy = busy.clone();
+        move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("failed to accept a connection: {e}");
+                        continue;
+                    }
+                };
+
+                if busy.swap(true, Ordering::AcqRel) {
+                    let response = Response::Error("a picker is already open".to_string());
+                    if let Err(e) = write_message(&mut stream, &response) {
+                        log::warn!("failed to respond to a client: {e:#}");
+                    }
+                    continue;
+                }
+
+                if sender.send(stream).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    for mut stream in receiver {
+        let response = stream
+            .set_read_timeout(Some(REQUEST_TIMEOUT))
+            .map_err(anyhow::Error::from)
+            .and_then(|()| read_message::<Request>(&mut stream))
+            .context("invalid request")
+            .and_then(|request| show(&mut context, load_config()?, &request));
+
+        let response = match response {
+            Ok(value) => Response::Selected(value),
+            Err(e) => {
+                log::error!("{e:#}");
+                Response::Error(format!("{e:#}"))
+            }
+        };
+
+        if let Err(e) = write_message(&mut stream, &response) {
+            log::warn!("failed to respond to a client: {e:#}");
+        }
+        drop(stream);
+
+        busy.store(false, Ordering::Release);
+
+        anyhow::ensure!(context.is_some(), "lost the connection to the compositor");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_compositor::Shell;
+
+    #[test]
+    fn test_message_round_trip() {
+        let request = Request {
+            choices: vec![
+                grid::Choice::new("a".to_string()),
+                grid::Choice {
+                    display: Some("two".to_string()),
+                    icon: Some("firefox".to_string()),
+                    children: vec![grid::Choice::new("2.1".to_string())],
+                    ..grid::Choice::new("2".to_string())
+                },
+            ],
+            initial_value: Some("2".to_string()),
+            format: input::Format::default(),
+        };
+
+        let mut buf = vec![];
+        write_message(&mut buf, &request).unwrap();
+        assert_eq!(
+            buf.len() - 4,
+            u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize
+        );
+
+        let read: Request = read_message(&mut buf.as_slice()).unwrap();
+        assert_eq!(request, read);
+    }
+
+    #[test]
+    fn test_message_too_large() {
+        let mut buf = (MAX_MESSAGE_LEN + 1).to_be_bytes().to_vec();
+        buf.extend_from_slice(b"{}");
+        assert!(read_message::<Response>(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_truncated_message() {
+        let mut buf = vec![];
+        let selection = state::Selection {
+            value: "a".to_string(),
+            display: "A".to_string(),
+            index: 0,
+        };
+        write_message(&mut buf, &Response::Selected(Some(selection))).unwrap();
+        buf.pop();
+        assert!(read_message::<Response>(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_messages_over_socket() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+
+        write_message(
+            &mut client,
+            &Request {
+                choices: vec![],
+                initial_value: None,
+                format: input::Format::default(),
+            },
+        )
+        .unwrap();
+        let request: Request = read_message(&mut server).unwrap();
+        assert!(request.choices.is_empty());
+
+        write_message(&mut server, &Response::Error("busy".to_string())).unwrap();
+        let response: Response = read_message(&mut client).unwrap();
+        assert_eq!(Response::Error("busy".to_string()), response);
+    }
+
+    #[test]
+    fn test_bad_request_keeps_context() {
+        let (conn, server) = Shell::spawn();
+        let font = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fonts/Tuffy.ttf");
+        let config = || config::Config {
+            fonts: vec![font.clone()],
+            ..config::Config::default()
+        };
+        let mut context = Some(window::Context::with_connection(conn, &config()).unwrap());
+
+        // too many items for the grid
+        let bad = Request {
+            choices: (0..1000)
+                .map(|i| grid::Choice::new(i.to_string()))
+                .collect(),
+            initial_value: None,
+            format: input::Format::default(),
+        };
+        assert!(show(&mut context, config(), &bad).is_err());
+        assert!(context.is_some());
+
+        // the compositor closes the picker straight away, so nothing is chosen
+        let good = Request {
+            choices: vec![grid::Choice::new("a".to_string())],
+            ..bad
+        };
+        assert_eq!(None, show(&mut context, config(), &good).unwrap());
+        assert!(context.is_some());
+
+        assert_eq!(1, server.stop().layers);
+    }
+}
@@ -0,0 +1,486 @@
+// an application launcher, listing the desktop entries installed on the system.
+//
+// https://specifications.freedesktop.org/desktop-entry-spec/latest/
+
+use crate::grid;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const APPLICATIONS_DIR: &str = "applications";
+
+// the parts of the environment that affect which entries are shown, and how.
+pub struct Environment {
+    // e.g. "de_AT.UTF-8@euro"
+    pub locale: Option<String>,
+    // the desktops listed in XDG_CURRENT_DESKTOP
+    pub desktops: Vec<String>,
+}
+
+impl Environment {
+    pub fn from_env() -> Environment {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|v| std::env::var(v).ok())
+            .find(|v| !v.is_empty());
+
+        let desktops = std::env::var("XDG_CURRENT_DESKTOP")
+            .map(|v| v.split(':').map(str::to_string).collect())
+            .unwrap_or_default();
+
+        Environment { locale, desktops }
+    }
+
+    // the suffixes to try for localised keys, most specific first.
+    // e.g. for "sr_YU@Latn": sr_YU@Latn, sr_YU, sr@Latn, sr
+    fn locale_keys(&self) -> Vec<String> {
+        let Some(locale) = self.locale.as_deref() else {
+            return vec![];
+        };
+
+        let (locale, modifier) = match locale.split_once('@') {
+            Some((l, m)) => (l, Some(m)),
+            None => (locale, None),
+        };
+        let locale = locale.split('.').next().unwrap_or(locale);
+        let (lang, country) = match locale.split_once('_') {
+            Some((l, c)) => (l, Some(c)),
+            None => (locale, None),
+        };
+
+        let mut keys = vec![];
+        if let (Some(c), Some(m)) = (country, modifier) {
+            keys.push(format!("{lang}_{c}@{m}"));
+        }
+        if let Some(c) = country {
+            keys.push(format!("{lang}_{c}"));
+        }
+        if let Some(m) = modifier {
+            keys.push(format!("{lang}@{m}"));
+        }
+        keys.push(lang.to_string());
+        keys
+    }
+}
+
+// the [Desktop Entry] group of a .desktop file.
+struct Group(HashMap<String, String>);
+
+impl Group {
+    fn parse(contents: &str) -> Group {
+        let mut entries = HashMap::new();
+        let mut in_group = false;
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_group = line == "[Desktop Entry]";
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=').filter(|_| in_group) {
+                entries
+                    .entry(key.trim().to_string())
+                    .or_insert_with(|| unescape(value.trim()));
+            }
+        }
+
+        Group(entries)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn get_localised(&self, key: &str, env: &Environment) -> Option<&str> {
+        env.locale_keys()
+            .iter()
+            .find_map(|l| self.get(&format!("{key}[{l}]")))
+            .or_else(|| self.get(key))
+    }
+
+    fn get_bool(&self, key: &str) -> bool {
+        self.get(key) == Some("true")
+    }
+
+    fn get_list(&self, key: &str) -> Vec<&str> {
+        self.get(key)
+            .map(|v| v.split(';').filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default()
+    }
+}
+
+// expand the escape sequences allowed in string values.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(other) => {
+                // leave "\;" alone so that lists can still be split.
+                if other == ';' {
+                    result.push('\\');
+                }
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+pub struct DesktopEntry {
+    // the desktop file ID, e.g. "org.gnome.Nautilus.desktop"
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub exec: String,
+    pub path: Option<PathBuf>,
+}
+
+impl DesktopEntry {
+    // parse an entry, returning None if it shouldn't be shown.
+    fn parse(id: String, contents: &str, env: &Environment) -> Option<DesktopEntry> {
+        let group = Group::parse(contents);
+
+        if group.get("Type") != Some("Application")
+            || group.get_bool("NoDisplay")
+            || group.get_bool("Hidden")
+        {
+            return None;
+        }
+
+        let only_show_in = group.get_list("OnlyShowIn");
+        if !only_show_in.is_empty()
+            && !env
+                .desktops
+                .iter()
+                .any(|d| only_show_in.contains(&d.as_str()))
+        {
+            return None;
+        }
+        let not_show_in = group.get_list("NotShowIn");
+        if env
+            .desktops
+            .iter()
+            .any(|d| not_show_in.contains(&d.as_str()))
+        {
+            return None;
+        }
+
+        Some(DesktopEntry {
+            id,
+            name: group.get_localised("Name", env)?.to_string(),
+            icon: group
+                .get_localised("Icon", env)
+                .filter(|i| !i.is_empty())
+                .map(str::to_string),
+            exec: group.get("Exec")?.to_string(),
+            path: group
+                .get("Path")
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+        })
+    }
+
+    pub fn choice(&self) -> grid::Choice {
+        grid::Choice {
+            display: Some(self.name.clone()),
+            icon: self.icon.clone(),
+            ..grid::Choice::new(self.id.clone())
+        }
+    }
+
+    // the command line to run, with field codes removed.
+    pub fn command(&self) -> Vec<String> {
+        split_exec(&self.exec)
+            .into_iter()
+            .filter_map(|arg| strip_field_codes(&arg))
+            .collect()
+    }
+
+    // start the application, without waiting for it to exit.
+    pub fn launch(&self) -> Result<()> {
+        let command = self.command();
+        let (program, args) = command
+            .split_first()
+            .with_context(|| format!("{} has an empty Exec key", self.id))?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        if let Some(path) = &self.path {
+            cmd.current_dir(path);
+        }
+        // don't take the application down with our terminal
+        cmd.process_group(0);
+
+        cmd.spawn()
+            .with_context(|| format!("failed to launch {}", self.id))?;
+        Ok(())
+    }
+}
+
+// split an Exec value into arguments, following the spec's quoting rules.
+fn split_exec(exec: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = exec.chars();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.get_or_insert_with(String::new);
+            }
+            '\\' if quoted => {
+                if let Some(escaped) = chars.next() {
+                    current.get_or_insert_with(String::new).push(escaped);
+                }
+            }
+            ' ' if !quoted => {
+                if let Some(arg) = current.take() {
+                    args.push(arg);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if let Some(arg) = current {
+        args.push(arg);
+    }
+
+    args
+}
+
+// remove the field codes from an argument. arguments that are entirely
+// a field code (e.g. "%U") are dropped.
+fn strip_field_codes(arg: &str) -> Option<String> {
+    let mut result = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+        } else if chars.next() == Some('%') {
+            result.push('%');
+        }
+    }
+
+    (!result.is_empty() || arg.is_empty()).then_some(result)
+}
+
+// quote an argument so that a shell would see it as a single word.
+pub fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./=:,+@%".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+// quote arguments so that a shell would split them back up the same way.
+pub fn shell_join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn desktop_files(dir: &Path, prefix: &str, found: &mut Vec<(String, PathBuf)>) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        if path.is_dir() {
+            desktop_files(&path, &format!("{prefix}{name}-"), found);
+        } else if name.ends_with(".desktop") {
+            found.push((format!("{prefix}{name}"), path));
+        }
+    }
+}
+
+// every desktop entry that should be shown, sorted by name.
+//
+// `data_dirs` are searched in order; an entry shadows entries with the same
+// desktop file ID in later directories, even if it's hidden.
+pub fn entries(data_dirs: &[PathBuf], env: &Environment) -> Vec<DesktopEntry> {
+    let mut seen = HashSet::new();
+    let mut entries = vec![];
+
+    for dir in data_dirs {
+        let mut found = vec![];
+        desktop_files(&dir.join(APPLICATIONS_DIR), "", &mut found);
+        found.sort();
+
+        for (id, path) in found {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(c) => c,
+                Err(e) => {
+                    log::warn!("failed to read {}: {e}", path.display());
+                    continue;
+                }
+            };
+            entries.extend(DesktopEntry::parse(id, &contents, env));
+        }
+    }
+
+    entries.sort_by_cached_key(|e| e.name.to_lowercase());
+    entries
+}
+
+// the icon of the application with the given app ID (e.g. a Wayland app_id),
+// from its desktop entry.
+pub fn find_icon(data_dirs: &[PathBuf], app_id: &str) -> Option<String> {
+    data_dirs.iter().find_map(|dir| {
+        let path = dir.join(APPLICATIONS_DIR).join(format!("{app_id}.desktop"));
+        let contents = std::fs::read_to_string(path).ok()?;
+        Group::parse(&contents)
+            .get("Icon")
+            .filter(|i| !i.is_empty())
+            .map(str::to_string)
+    })
+}
+
+// $XDG_DATA_HOME followed by $XDG_DATA_DIRS
+pub fn data_dirs() -> Vec<PathBuf> {
+    let dirs = xdg::BaseDirectories::new();
+    dirs.get_data_home()
+        .into_iter()
+        .chain(dirs.get_data_dirs())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> Vec<PathBuf> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/drun");
+        vec![root.join("home"), root.join("system")]
+    }
+
+    fn env(locale: Option<&str>, desktops: &[&str]) -> Environment {
+        Environment {
+            locale: locale.map(str::to_string),
+            desktops: desktops.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    fn ids(entries: &[DesktopEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_entries() {
+        let entries = entries(&fixtures(), &env(None, &["sway"]));
+
+        // sorted by name. hidden, nodisplay, gnome-only and the shadowed
+        // system editor are excluded.
+        assert_eq!(
+            vec![
+                "org.example.Browser.desktop",
+                "editor.desktop",
+                "tools-terminal.desktop"
+            ],
+            ids(&entries)
+        );
+
+        let editor = &entries[1];
+        assert_eq!("My Editor", editor.name);
+        assert_eq!(vec!["my-editor", "--new-window"], editor.command());
+    }
+
+    #[test]
+    fn test_only_show_in() {
+        let entries = entries(&fixtures(), &env(None, &["GNOME"]));
+        assert!(ids(&entries).contains(&"gnome-settings.desktop"));
+    }
+
+    #[test]
+    fn test_localised_name() {
+        let entries = entries(&fixtures(), &env(Some("de_AT.UTF-8"), &[]));
+        let browser = entries
+            .iter()
+            .find(|e| e.id == "org.example.Browser.desktop")
+            .unwrap();
+        assert_eq!("Netzbrowser", browser.name);
+    }
+
+    #[test]
+    fn test_find_icon() {
+        assert_eq!(
+            Some("web-browser".to_string()),
+            find_icon(&fixtures(), "org.example.Browser")
+        );
+        assert_eq!(None, find_icon(&fixtures(), "org.example.Missing"));
+    }
+
+    #[test]
+    fn test_locale_keys() {
+        assert_eq!(
+            vec!["sr_YU@Latn", "sr_YU", "sr@Latn", "sr"],
+            env(Some("sr_YU.UTF-8@Latn"), &[]).locale_keys()
+        );
+        assert_eq!(vec!["C"], env(Some("C"), &[]).locale_keys());
+        assert!(env(None, &[]).locale_keys().is_empty());
+    }
+
+    #[test]
+    fn test_shell_join() {
+        assert_eq!(
+            r#"app --title 'it'\''s here' ''"#,
+            shell_join(&[
+                "app".to_string(),
+                "--title".to_string(),
+                "it's here".to_string(),
+                "".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_command() {
+        let entry = DesktopEntry {
+            id: "test.desktop".to_string(),
+            name: "Test".to_string(),
+            icon: None,
+            exec: r#"test --title "a \"quoted\" title" --percent 100%% %U"#.to_string(),
+            path: None,
+        };
+        assert_eq!(
+            vec![
+                "test",
+                "--title",
+                r#"a "quoted" title"#,
+                "--percent",
+                "100%"
+            ],
+            entry.command()
+        );
+    }
+}
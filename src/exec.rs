@@ -0,0 +1,168 @@
+// running a command when an item is chosen, instead of printing its value.
+//
+// the command is a template that's run with `sh -c`. "{value}", "{display}"
+// and "{index}" are replaced by the chosen item's, quoted so that the shell
+// sees each of them as a single word. "{{" and "}}" are literal braces.
+
+use crate::{drun, state};
+use anyhow::{Context, Result};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum Part {
+    Text(String),
+    Value,
+    Display,
+    Index,
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    // check the template up front, so that a mistake in it is found before
+    // anything is chosen.
+    pub fn parse(template: &str) -> Result<Template> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(i) = rest.find(['{', '}']) {
+            text.push_str(&rest[..i]);
+            rest = &rest[i..];
+
+            if let Some(r) = rest.strip_prefix("{{") {
+                text.push('{');
+                rest = r;
+                continue;
+            }
+            if let Some(r) = rest.strip_prefix("}}") {
+                text.push('}');
+                rest = r;
+                continue;
+            }
+
+            let end = rest
+                .find('}')
+                .filter(|_| rest.starts_with('{'))
+                .with_context(|| format!("unmatched brace in {template:?} (use {{{{ or }}}})"))?;
+            let part = match &rest[1..end] {
+                "value" => Part::Value,
+                "display" => Part::Display,
+                "index" => Part::Index,
+                name => anyhow::bail!(
+                    "unknown placeholder {{{name}}} in {template:?}, expected {{value}}, {{display}} or {{index}}"
+                ),
+            };
+            parts.push(Part::Text(std::mem::take(&mut text)));
+            parts.push(part);
+            rest = &rest[end + 1..];
+        }
+
+        text.push_str(rest);
+        parts.push(Part::Text(text));
+        parts.retain(|p| !matches!(p, Part::Text(t) if t.is_empty()));
+
+        Ok(Template { parts })
+    }
+
+    // the shell command for the chosen item.
+    pub fn expand(&self, selection: &state::Selection) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Value => drun::shell_quote(&selection.value),
+                Part::Display => drun::shell_quote(&selection.display),
+                Part::Index => selection.index.to_string(),
+            })
+            .collect()
+    }
+
+    // start the command for the chosen item, without waiting for it to exit.
+    pub fn spawn(&self, selection: &state::Selection) -> Result<()> {
+        let command = self.expand(selection);
+        Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .stdin(Stdio::null())
+            // don't take the command down with our terminal
+            .process_group(0)
+            .spawn()
+            .with_context(|| format!("failed to run {command:?}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn selection(value: &str, display: &str, index: usize) -> state::Selection {
+        state::Selection {
+            value: value.to_string(),
+            display: display.to_string(),
+            index,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let template =
+            Template::parse("hyprctl dispatch workspace {value} # {display} {index}").unwrap();
+        assert_eq!(
+            "hyprctl dispatch workspace 3 # 'three: it'\\''s $HOME' 2",
+            template.expand(&selection("3", "three: it's $HOME", 2))
+        );
+
+        let template = Template::parse("echo {{value}} {{{value}}}").unwrap();
+        assert_eq!("echo {value} {''}", template.expand(&selection("", "", 0)));
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(Template::parse("echo {name}").is_err());
+        assert!(Template::parse("echo {value").is_err());
+        assert!(Template::parse("echo value}").is_err());
+        assert!(Template::parse("echo }{").is_err());
+        assert_eq!(
+            Template {
+                parts: vec![Part::Text("true".to_string())]
+            },
+            Template::parse("true").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_spawn() {
+        let path =
+            std::env::temp_dir().join(format!("grid-select-test-exec-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let template = Template::parse(&format!(
+            "printf '%s|%s|%s' {{value}} {{display}} {{index}} > {}",
+            drun::shell_quote(path.to_str().unwrap())
+        ))
+        .unwrap();
+        template
+            .spawn(&selection("a b", "it's; rm -rf /", 7))
+            .unwrap();
+
+        // the command is detached, so wait for it to write the file
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let contents = loop {
+            match std::fs::read_to_string(&path) {
+                Ok(contents) if !contents.is_empty() => break contents,
+                _ if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                _ => panic!("the command didn't run"),
+            }
+        };
+        assert_eq!("a b|it's; rm -rf /|7", contents);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
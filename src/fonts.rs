@@ -0,0 +1,418 @@
+// loading fonts, which is the slowest part of starting up.
+//
+// by default every system font is loaded (like FontSystem::new). there are two
+// ways to avoid that:
+//
+// - list the font files and directories to load in the `fonts` config option.
+// - set `font_name`. the files for that family are found in a cache of the
+//   system font database, and only they are loaded, along with whichever of
+//   cosmic-text's fallback families are installed: the common ones (Noto Sans,
+//   DejaVu Sans, symbols and emoji) and those for other scripts (e.g. Noto Sans
+//   CJK). the cache is rebuilt when one of the directories containing fonts
+//   has been modified.
+//
+// either way, characters that aren't in the loaded fonts can't be drawn.
+
+use crate::{config, file};
+use anyhow::{Context, Result};
+use cosmic_text::{Fallback, FontSystem, PlatformFallback, fontdb};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use unicode_script::Script;
+
+const CACHE_FILE: &str = "fonts.json";
+
+// bumped when the cache format changes.
+const CACHE_VERSION: u32 = 1;
+
+// the scripts that cosmic-text has fallback families for (see
+// PlatformFallback::script_fallback). the items haven't been read when the
+// fonts are loaded, so the fallbacks for all of them are loaded.
+const FALLBACK_SCRIPTS: &[Script] = &[
+    Script::Adlam,
+    Script::Arabic,
+    Script::Armenian,
+    Script::Bengali,
+    Script::Bopomofo,
+    Script::Braille,
+    Script::Buhid,
+    Script::Chakma,
+    Script::Cherokee,
+    Script::Deseret,
+    Script::Devanagari,
+    Script::Ethiopic,
+    Script::Georgian,
+    Script::Gothic,
+    Script::Grantha,
+    Script::Gujarati,
+    Script::Gurmukhi,
+    Script::Han,
+    Script::Hangul,
+    Script::Hanunoo,
+    Script::Hebrew,
+    Script::Hiragana,
+    Script::Javanese,
+    Script::Kannada,
+    Script::Katakana,
+    Script::Khmer,
+    Script::Lao,
+    Script::Malayalam,
+    Script::Mongolian,
+    Script::Myanmar,
+    Script::Oriya,
+    Script::Runic,
+    Script::Sinhala,
+    Script::Syriac,
+    Script::Tagalog,
+    Script::Tagbanwa,
+    Script::Tai_Le,
+    Script::Tai_Tham,
+    Script::Tai_Viet,
+    Script::Tamil,
+    Script::Telugu,
+    Script::Thaana,
+    Script::Thai,
+    Script::Tibetan,
+    Script::Tifinagh,
+    Script::Vai,
+    Script::Yi,
+];
+
+// the system font database, reduced to the files that each family is in.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+struct Cache {
+    version: u32,
+    // every directory that contains fonts, with the time it was last modified
+    dirs: BTreeMap<PathBuf, Option<SystemTime>>,
+    // lowercase family name => font files
+    families: BTreeMap<String, BTreeSet<PathBuf>>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// the directories whose modification means that fonts may have been added or removed.
+//
+// that's the directory containing the font, and its ancestors up to the font
+// directory root (e.g. /usr/share/fonts), so that new subdirectories are noticed.
+// a font that isn't under one (e.g. in a directory from the fontconfig config)
+// only has its own directory watched.
+fn watched_dirs(font_file: &Path) -> impl Iterator<Item = &Path> {
+    let dirs = font_file.ancestors().skip(1);
+    let root = dirs.clone().position(|dir| {
+        let name = dir.file_name().and_then(|n| n.to_str());
+        matches!(name, Some("fonts" | ".fonts"))
+    });
+    dirs.take(root.map_or(1, |i| i + 1))
+}
+
+impl Cache {
+    fn from_db(db: &fontdb::Database) -> Cache {
+        let mut cache = Cache {
+            version: CACHE_VERSION,
+            dirs: BTreeMap::new(),
+            families: BTreeMap::new(),
+        };
+
+        for face in db.faces() {
+            let fontdb::Source::File(path) = &face.source else {
+                continue;
+            };
+
+            for dir in watched_dirs(path) {
+                cache
+                    .dirs
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| modified(dir));
+            }
+
+            for (family, _) in &face.families {
+                cache
+                    .families
+                    .entry(family.to_lowercase())
+                    .or_default()
+                    .insert(path.clone());
+            }
+        }
+
+        cache
+    }
+
+    // is this still an accurate record of the system fonts?
+    fn is_fresh(&self) -> bool {
+        self.version == CACHE_VERSION
+            && self.dirs.iter().all(|(dir, mtime)| modified(dir) == *mtime)
+    }
+
+    fn family_files(&self, family: &str) -> Option<&BTreeSet<PathBuf>> {
+        self.families.get(&family.to_lowercase())
+    }
+
+    // the files for a family, and for whichever fallbacks are installed.
+    // `locale` decides which fallbacks are used for Chinese characters.
+    fn files_with_fallbacks(&self, family: &str, locale: &str) -> Option<BTreeSet<&PathBuf>> {
+        let mut files: BTreeSet<_> = self.family_files(family)?.iter().collect();
+        let script_fallbacks = FALLBACK_SCRIPTS
+            .iter()
+            .flat_map(|script| PlatformFallback.script_fallback(*script, locale));
+        for fallback in PlatformFallback
+            .common_fallback()
+            .iter()
+            .chain(script_fallbacks)
+        {
+            files.extend(self.family_files(fallback).into_iter().flatten());
+        }
+        Some(files)
+    }
+
+    fn load(path: &Path) -> Option<Cache> {
+        let data = std::fs::read(path).ok()?;
+        serde_json::from_slice(&data)
+            .inspect_err(|e| log::warn!("ignoring invalid font cache {}: {e}", path.display()))
+            .ok()
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_vec(self)?;
+        file::write_atomic(path, &data)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+fn cache_path() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix(crate::prog_name!())
+        .place_cache_file(CACHE_FILE)
+        .inspect_err(|e| log::warn!("can't cache the font database: {e}"))
+        .ok()
+}
+
+fn locale() -> String {
+    sys_locale::get_locale().unwrap_or_else(|| String::from("en-US"))
+}
+
+fn system_database() -> fontdb::Database {
+    let mut db = fontdb::Database::new();
+    db.load_system_fonts();
+    db
+}
+
+// load the fonts at the given paths, which may be files or directories.
+pub(crate) fn database_from_paths<'a>(
+    paths: impl IntoIterator<Item = &'a PathBuf>,
+) -> fontdb::Database {
+    let mut db = fontdb::Database::new();
+    for path in paths {
+        if path.is_dir() {
+            db.load_fonts_dir(path);
+        } else if let Err(e) = db.load_font_file(path) {
+            log::warn!("failed to load font {}: {e}", path.display());
+        }
+    }
+    db
+}
+
+// a database containing the given family, using the cache if we can. None if
+// the cache says that the family isn't installed.
+fn family_database(family: &str) -> Option<fontdb::Database> {
+    let path = cache_path();
+
+    if let Some(cache) = path
+        .as_deref()
+        .and_then(Cache::load)
+        .filter(Cache::is_fresh)
+    {
+        let files = cache.files_with_fallbacks(family, &locale());
+        if files.is_none() {
+            log::warn!("font {family:?} isn't installed");
+        }
+        return files.map(database_from_paths);
+    }
+
+    // the cache is missing or out of date, so we need to look at every font anyway.
+    let db = system_database();
+    if let Some(path) = path
+        && let Err(e) = Cache::from_db(&db).save(&path)
+    {
+        log::warn!("{e:#}");
+    }
+    Some(db)
+}
+
+pub fn font_system(config: &config::Config) -> FontSystem {
+    let mut db = match (&config.fonts, &config.font_name) {
+        (fonts, _) if !fonts.is_empty() => database_from_paths(fonts),
+        (_, Some(font_name)) => match family_database(font_name) {
+            Some(db) => db,
+            // the cache is up to date, so there's no point rebuilding it
+            None => return FontSystem::new(),
+        },
+        (_, None) => return FontSystem::new(),
+    };
+
+    // text without a font_name is drawn in the default sans-serif font. that
+    // may not have been loaded, so use one that has.
+    let first_family = db
+        .faces()
+        .next()
+        .and_then(|f| f.families.first())
+        .map(|(family, _)| family.clone());
+    if config.font_name.is_none()
+        && let Some(family) = first_family
+    {
+        db.set_sans_serif_family(family);
+    }
+
+    log::debug!("loaded {} font faces", db.len());
+
+    FontSystem::new_with_locale_and_db(locale(), db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "grid-select-test-fonts-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_watched_dirs() {
+        let dirs: Vec<_> =
+            watched_dirs(Path::new("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf")).collect();
+        assert_eq!(
+            vec![
+                Path::new("/usr/share/fonts/truetype/dejavu"),
+                Path::new("/usr/share/fonts/truetype"),
+                Path::new("/usr/share/fonts"),
+            ],
+            dirs
+        );
+
+        // not under a fonts directory: only watch its own directory
+        let dirs: Vec<_> = watched_dirs(Path::new("/opt/inter/web/Inter.ttf")).collect();
+        assert_eq!(vec![Path::new("/opt/inter/web")], dirs);
+    }
+
+    #[test]
+    fn test_freshness() {
+        let dir = temp_dir("freshness");
+        let cache = Cache {
+            version: CACHE_VERSION,
+            dirs: BTreeMap::from([(dir.clone(), modified(&dir))]),
+            families: BTreeMap::new(),
+        };
+        assert!(cache.is_fresh());
+
+        // adding a font changes the directory's modification time
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(dir.join("new.ttf"), b"").unwrap();
+        assert!(!cache.is_fresh());
+
+        let old = Cache {
+            version: CACHE_VERSION - 1,
+            dirs: BTreeMap::new(),
+            families: BTreeMap::new(),
+        };
+        assert!(!old.is_fresh());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = temp_dir("save");
+        let cache = Cache {
+            version: CACHE_VERSION,
+            dirs: BTreeMap::from([(dir.clone(), modified(&dir))]),
+            families: BTreeMap::from([(
+                "dejavu sans".to_string(),
+                BTreeSet::from([PathBuf::from("/usr/share/fonts/DejaVuSans.ttf")]),
+            )]),
+        };
+
+        let path = dir.join(CACHE_FILE);
+        cache.save(&path).unwrap();
+        let loaded = Cache::load(&path).unwrap();
+
+        assert_eq!(cache, loaded);
+        assert!(loaded.family_files("DejaVu Sans").is_some());
+        assert!(loaded.family_files("Inter").is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let files = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<BTreeSet<_>>();
+        let cache = Cache {
+            version: CACHE_VERSION,
+            dirs: BTreeMap::new(),
+            families: BTreeMap::from([
+                ("inter".to_string(), files(&["/fonts/Inter.ttf"])),
+                ("noto sans".to_string(), files(&["/fonts/NotoSans.ttf"])),
+                (
+                    "noto color emoji".to_string(),
+                    files(&["/fonts/NotoColorEmoji.ttf"]),
+                ),
+                (
+                    "noto sans arabic".to_string(),
+                    files(&["/fonts/NotoSansArabic.ttf"]),
+                ),
+                (
+                    "noto sans cjk sc".to_string(),
+                    files(&["/fonts/NotoSansCJKsc.otf"]),
+                ),
+                (
+                    "noto sans cjk tc".to_string(),
+                    files(&["/fonts/NotoSansCJKtc.otf"]),
+                ),
+                (
+                    "tex gyre adventor".to_string(),
+                    files(&["/fonts/Adventor.otf"]),
+                ),
+            ]),
+        };
+
+        let loaded: Vec<_> = cache
+            .files_with_fallbacks("Inter", "en-GB")
+            .unwrap()
+            .into_iter()
+            .cloned()
+            .collect();
+        assert_eq!(
+            vec![
+                PathBuf::from("/fonts/Inter.ttf"),
+                PathBuf::from("/fonts/NotoColorEmoji.ttf"),
+                PathBuf::from("/fonts/NotoSans.ttf"),
+                PathBuf::from("/fonts/NotoSansArabic.ttf"),
+                PathBuf::from("/fonts/NotoSansCJKsc.otf"),
+            ],
+            loaded
+        );
+
+        // the locale decides how Chinese characters are drawn
+        let tc = PathBuf::from("/fonts/NotoSansCJKtc.otf");
+        let taiwan = cache.files_with_fallbacks("Inter", "zh-TW").unwrap();
+        assert!(taiwan.contains(&tc));
+
+        // a fallback that's also the chosen family is only loaded once
+        assert_eq!(
+            4,
+            cache
+                .files_with_fallbacks("noto sans", "en-GB")
+                .unwrap()
+                .len()
+        );
+        assert!(cache.files_with_fallbacks("Comic Sans", "en-GB").is_none());
+    }
+}
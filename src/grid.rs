@@ -1,10 +1,21 @@
 use crate::spiral;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
 
 // the largest grid that we'll accept.
 // we're too slow to handle large grids right now.
 // dimension 7 fits 25 items.
 const MAX_DIMENSION: usize = 7;
 
+// the most columns that sections can take up side by side: room for three of
+// the largest sections. any more and the window gets wider than most screens.
+const MAX_WIDTH: usize = 3 * MAX_DIMENSION + 2;
+
+// the most items that fit in the largest grid.
+pub fn max_items() -> usize {
+    spiral::total_positions_within(MAX_DIMENSION / 2)
+}
+
 // an x/y coordinate, relative to the centre position.
 #[derive(Clone, PartialEq)]
 #[cfg_attr(test, derive(Debug))]
@@ -19,23 +30,277 @@ impl GridPosition {
     }
 }
 
+// one of the options that the user can choose between.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Choice {
+    // written to stdout when the item is chosen
+    pub value: String,
+    // shown to the user, if it's different to the value
+    pub display: Option<String>,
+    // an icon name or image path
+    pub icon: Option<String>,
+    // choices in the same group are shown together, under the group's name
+    #[serde(default)]
+    pub group: Option<String>,
+    // choosing this shows these choices instead
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Choice>,
+    // choosing this shows the choices that this shell command prints instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    // where the choice was in the input, counting from 0, if it's been moved
+    // since (e.g. by its history)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_index: Option<usize>,
+}
+
+impl Choice {
+    pub fn new(value: String) -> Choice {
+        Choice {
+            value,
+            display: None,
+            icon: None,
+            group: None,
+            children: vec![],
+            command: None,
+            input_index: None,
+        }
+    }
+
+    // the menu that choosing this opens, if it's not an ordinary choice.
+    pub fn submenu(&self) -> Option<Submenu> {
+        match &self.command {
+            Some(command) => Some(Submenu::Command(command.clone())),
+            None if !self.children.is_empty() => Some(Submenu::Children(self.children.clone())),
+            None => None,
+        }
+    }
+}
+
+// a grid that's shown in place of the current one when an item is chosen.
+#[derive(Clone)]
+pub enum Submenu {
+    Children(Vec<Choice>),
+    // the lines that a shell command prints, one choice per line
+    Command(String),
+}
+
+// the item that the cursor should start on.
+pub enum Preselect {
+    // the first choice with this value
+    Value(String),
+    // the nth choice, counting from 0
+    Index(usize),
+    // the first choice that's shown with this text
+    Display(String),
+}
+
+impl Preselect {
+    // is `choice`, the `index`th choice that we were given, the preselected one?
+    pub fn matches(&self, index: usize, choice: &Choice) -> bool {
+        match self {
+            Preselect::Value(value) => &choice.value == value,
+            Preselect::Index(i) => index == *i,
+            Preselect::Display(display) => {
+                choice.display.as_ref().unwrap_or(&choice.value) == display
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct GridItem {
     pub value: String,
     pub display: String,
+    pub icon: Option<String>,
+    pub submenu: Option<Submenu>,
+    // where the item is in the choices that the grid was made from
+    pub index: usize,
+    // where the item was in the input
+    pub input_index: usize,
     pub position: GridPosition,
 }
 
+// a cluster of items, laid out in its own spiral.
+pub struct Section {
+    // shown above the section's items, if the grid is divided into sections
+    pub title: Option<String>,
+    // the position of the section's first item
+    pub centre: GridPosition,
+    // the section's first and last columns
+    pub left: i8,
+    pub right: i8,
+    // the section's items are at indexes first..first + len
+    pub first: usize,
+    pub len: usize,
+}
+
+impl Section {
+    // the position of the section's nth item.
+    pub fn position_at(&self, n: usize) -> GridPosition {
+        let p = spiral::position_at(n);
+        GridPosition::new(self.centre.x + p.x, self.centre.y + p.y)
+    }
+
+    // how many items into the section the one at `position` would be.
+    pub fn index_of(&self, position: &GridPosition) -> usize {
+        spiral::index_of(&GridPosition::new(
+            position.x.saturating_sub(self.centre.x),
+            position.y.saturating_sub(self.centre.y),
+        ))
+    }
+}
+
+// where each section is.
+struct Layout {
+    sections: Vec<Section>,
+    // the section in each column, from left to right
+    columns: Vec<Option<usize>>,
+    // the leftmost column
+    left: i8,
+}
+
+impl Layout {
+    fn section_at(&self, x: i8) -> Option<&Section> {
+        let column = usize::try_from(x.checked_sub(self.left)?).ok()?;
+        let section = (*self.columns.get(column)?)?;
+        Some(&self.sections[section])
+    }
+}
+
+// things that are laid out in a grid, stored section by section in spiral order.
+//
+// they can be looked up by their index or by their position in constant time,
+// because each position has a fixed place in its section's spiral.
+pub struct Items<T> {
+    items: Vec<T>,
+    layout: Rc<Layout>,
+}
+
+impl<T> Items<T> {
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items.get(index)
+    }
+
+    pub fn at(&self, position: &GridPosition) -> Option<&T> {
+        self.get(self.index_at(position)?)
+    }
+
+    // the index of the item at `position`, if there is one.
+    pub fn index_at(&self, position: &GridPosition) -> Option<usize> {
+        let section = self.layout.section_at(position.x)?;
+        let n = section.index_of(position);
+        (n < section.len).then_some(section.first + n)
+    }
+
+    // the position of the item at `index`.
+    pub fn position_of(&self, index: usize) -> Option<GridPosition> {
+        let section = self
+            .sections()
+            .iter()
+            .find(|s| (s.first..s.first + s.len).contains(&index))?;
+        Some(section.position_at(index - section.first))
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.layout.sections
+    }
+
+    pub fn section_at(&self, position: &GridPosition) -> Option<&Section> {
+        self.layout.section_at(position.x)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // section by section, from the centre outwards.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    // empty the store, returning what was in it, e.g. so that it can be reused
+    // for the next grid.
+    pub fn take(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.items)
+    }
+
+    // a store of other things at the same positions, e.g. the drawable version of each item.
+    pub fn map<U>(&self, mut f: impl FnMut(usize, &T) -> U) -> Items<U> {
+        Items {
+            items: self
+                .items
+                .iter()
+                .enumerate()
+                .map(|(i, t)| f(i, t))
+                .collect(),
+            layout: self.layout.clone(),
+        }
+    }
+}
+
 pub struct Grid {
     pub width: usize,
     pub height: usize,
 
-    pub grid: Vec<Vec<Option<GridItem>>>,
+    pub items: Items<GridItem>,
+}
+
+// a group's name, and its choices with their places in the input.
+type Group<'a> = (Option<&'a str>, Vec<(usize, &'a Choice)>);
+
+// the choices in each group, with the groups in the order that they first appear.
+fn group(choices: &[Choice]) -> Vec<Group<'_>> {
+    let mut groups: Vec<Group> = vec![];
+    for (index, choice) in choices.iter().enumerate() {
+        let name = choice.group.as_deref();
+        match groups.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, members)) => members.push((index, choice)),
+            None => groups.push((name, vec![(index, choice)])),
+        }
+    }
+    groups
+}
+
+// how many columns each group's section is.
+fn diameters(groups: &[Group]) -> Vec<usize> {
+    groups
+        .iter()
+        .map(|(_, members)| spiral::sufficient_diameter(members.len()))
+        .collect()
+}
+
+// how many columns the sections take up together.
+fn width(diameters: &[usize]) -> usize {
+    // an odd number of odd widths, plus the gaps between them, is always odd.
+    diameters.iter().sum::<usize>() + diameters.len().saturating_sub(1)
+}
+
+// can these choices be laid out in a grid? see Grid::new.
+pub fn fits(choices: &[Choice]) -> bool {
+    let diameters = diameters(&group(choices));
+    diameters.iter().all(|d| *d <= MAX_DIMENSION) && width(&diameters) <= MAX_WIDTH
 }
 
 impl Grid {
-    pub fn new(item_names: &[(String, Option<String>)]) -> anyhow::Result<Grid> {
-        let dimension = spiral::sufficient_diameter(item_names.len());
+    // without any groups, the choices are laid out in a single spiral around
+    // the centre of the grid.
+    //
+    // otherwise each group is a section, laid out in its own spiral. the
+    // sections are side by side with a column between them, and their tops
+    // lined up under a row of titles. an empty row at the bottom balances the
+    // titles, so that the centre of the grid is still at 0, 0.
+    pub fn new(choices: &[Choice]) -> anyhow::Result<Grid> {
+        let groups = group(choices);
+        let titled = choices.iter().any(|c| c.group.is_some());
+
+        let diameters = diameters(&groups);
+        let dimension = diameters.iter().copied().max().unwrap_or(0);
         anyhow::ensure!(
             dimension <= MAX_DIMENSION,
             "grid is too large: {} > {}",
@@ -43,76 +308,261 @@ impl Grid {
             MAX_DIMENSION
         );
 
-        let mut grid: Vec<Vec<Option<GridItem>>> = Vec::with_capacity(dimension);
-        for _ in 0..dimension {
-            grid.push(vec![None; dimension]);
-        }
+        let width = width(&diameters);
+        anyhow::ensure!(
+            width <= MAX_WIDTH,
+            "too many groups: {} columns are needed, but only {} fit",
+            width,
+            MAX_WIDTH
+        );
+        let height = if titled { dimension + 2 } else { dimension };
+        let top = -((dimension / 2) as i8);
 
-        let mut result = Grid {
-            width: dimension,
-            height: dimension,
-            grid,
+        let mut layout = Layout {
+            sections: vec![],
+            columns: vec![],
+            left: -((width / 2) as i8),
         };
+        let mut items = vec![];
 
-        let positions = spiral::SpiralGenerator::new();
-        for ((item_value, item_display), pos) in item_names.iter().zip(positions) {
-            let (idx_x, idx_y) = result.rel_to_abs(pos.x, pos.y).unwrap();
-            let item = GridItem {
-                // TODO: unnecessary copies?
-                value: item_value.clone(),
-                display: item_display.clone().unwrap_or_else(|| item_value.clone()),
-                position: pos,
-            };
-            result.grid[idx_x][idx_y] = Some(item);
-        }
+        for ((title, members), diameter) in groups.into_iter().zip(diameters) {
+            if !layout.columns.is_empty() {
+                layout.columns.push(None);
+            }
 
-        Ok(result)
-    }
+            let radius = (diameter / 2) as i8;
+            let left = layout.left + layout.columns.len() as i8;
+            let section = Section {
+                title: title.filter(|_| titled).map(str::to_string),
+                centre: GridPosition::new(left + radius, top + radius),
+                left,
+                right: left + diameter as i8 - 1,
+                first: items.len(),
+                len: members.len(),
+            };
 
-    // convert a position relative to the centre of the grid to a position in our 2d vec
-    fn rel_to_abs(&self, x: i8, y: i8) -> Option<(usize, usize)> {
-        let width = self.width;
-        let height = self.height;
+            layout
+                .columns
+                .extend(std::iter::repeat_n(Some(layout.sections.len()), diameter));
 
-        let idx_x = (width / 2).checked_add_signed(x as isize)?;
-        let idx_y = (height / 2).checked_add_signed(y as isize)?;
+            for (n, (index, choice)) in members.into_iter().enumerate() {
+                items.push(GridItem {
+                    // TODO: unnecessary copies?
+                    value: choice.value.clone(),
+                    display: choice
+                        .display
+                        .clone()
+                        .unwrap_or_else(|| choice.value.clone()),
+                    icon: choice.icon.clone(),
+                    submenu: choice.submenu(),
+                    index,
+                    input_index: choice.input_index.unwrap_or(index),
+                    position: section.position_at(n),
+                });
+            }
 
-        if idx_x >= width || idx_y >= height {
-            return None;
+            layout.sections.push(section);
         }
 
-        Some((idx_x, idx_y))
+        Ok(Grid {
+            width,
+            height,
+            items: Items {
+                items,
+                layout: Rc::new(layout),
+            },
+        })
     }
 
     pub fn item_at(&self, x: i8, y: i8) -> Option<&GridItem> {
-        let (idx_x, idx_y) = self.rel_to_abs(x, y)?;
-        self.grid[idx_x][idx_y].as_ref()
+        self.items.at(&GridPosition::new(x, y))
     }
 
-    pub fn items_iter(&self) -> GridSpiralIterator<'_> {
-        GridSpiralIterator::new(self)
+    pub fn items_iter(&self) -> std::slice::Iter<'_, GridItem> {
+        self.items.iter()
     }
-}
 
-pub struct GridSpiralIterator<'a> {
-    grid: &'a Grid,
-    positions: spiral::SpiralGenerator,
+    // the row that section titles are drawn in, if there is one.
+    pub fn title_row(&self) -> Option<i8> {
+        let titled = self.items.sections().iter().any(|s| s.title.is_some());
+        titled.then_some(-((self.height / 2) as i8))
+    }
 }
 
-impl GridSpiralIterator<'_> {
-    pub fn new(grid: &Grid) -> GridSpiralIterator<'_> {
-        GridSpiralIterator {
-            grid,
-            positions: spiral::SpiralGenerator::new(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choices() -> Vec<Choice> {
+        vec![
+            Choice::new("a".to_string()),
+            Choice {
+                display: Some("two".to_string()),
+                ..Choice::new("2".to_string())
+            },
+        ]
+    }
+
+    // the value of the first matching choice.
+    fn resolve<'a>(preselect: &Preselect, choices: &'a [Choice]) -> Option<&'a str> {
+        choices
+            .iter()
+            .enumerate()
+            .find(|(i, c)| preselect.matches(*i, c))
+            .map(|(_, c)| c.value.as_str())
+    }
+
+    #[test]
+    fn test_preselect() {
+        let choices = choices();
+
+        assert_eq!(Some("a"), resolve(&Preselect::Value("a".into()), &choices));
+        assert_eq!(Some("2"), resolve(&Preselect::Index(1), &choices));
+        assert_eq!(
+            Some("2"),
+            resolve(&Preselect::Display("two".into()), &choices)
+        );
+        assert_eq!(
+            Some("a"),
+            resolve(&Preselect::Display("a".into()), &choices)
+        );
+
+        assert_eq!(None, resolve(&Preselect::Value("two".into()), &choices));
+        assert_eq!(None, resolve(&Preselect::Index(2), &choices));
+        assert_eq!(None, resolve(&Preselect::Display("2".into()), &choices));
+    }
+
+    #[test]
+    fn test_spiral_placement() {
+        let grid = Grid::new(&choices()).unwrap();
+
+        assert_eq!("a", grid.item_at(0, 0).unwrap().value);
+        let values: Vec<_> = grid.items_iter().map(|i| i.value.as_str()).collect();
+        assert_eq!(vec!["a", "2"], values);
+    }
+
+    #[test]
+    fn test_item_lookup() {
+        let choices: Vec<_> = (0..max_items())
+            .map(|i| Choice::new(i.to_string()))
+            .collect();
+        let grid = Grid::new(&choices).unwrap();
+
+        for (i, position) in spiral::SpiralGenerator::new().take(max_items()).enumerate() {
+            let item = grid.item_at(position.x, position.y).unwrap();
+            assert_eq!(i.to_string(), item.value);
+            assert_eq!(position, item.position);
+            assert_eq!(i.to_string(), grid.items.get(i).unwrap().value);
         }
+
+        // in the corners of the square, but outside the diamond
+        assert!(grid.item_at(3, 3).is_none());
+        assert!(grid.item_at(-3, 1).is_none());
+        assert!(grid.item_at(i8::MIN, i8::MAX).is_none());
     }
-}
 
-impl<'a> Iterator for GridSpiralIterator<'a> {
-    type Item = &'a GridItem;
+    fn grouped(groups: &[(&str, usize)]) -> Vec<Choice> {
+        groups
+            .iter()
+            .flat_map(|(group, n)| {
+                (0..*n).map(move |i| Choice {
+                    group: Some(group.to_string()),
+                    ..Choice::new(format!("{group}{i}"))
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sections() {
+        let mut choices = grouped(&[("a", 3), ("b", 1), ("c", 6)]);
+        // groups are in order of their first item
+        choices.swap(1, 4);
+        let grid = Grid::new(&choices).unwrap();
+
+        // 3 + 5 + 1 columns, with a gap between each section
+        assert_eq!(11, grid.width);
+        // a row for the titles, and one to balance it
+        assert_eq!(7, grid.height);
+        assert_eq!(Some(-3), grid.title_row());
+
+        let sections: Vec<_> = grid
+            .items
+            .sections()
+            .iter()
+            .map(|s| {
+                (
+                    s.title.as_deref().unwrap(),
+                    (s.centre.x, s.centre.y),
+                    (s.left, s.right),
+                    (s.first, s.len),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("a", (-4, -1), (-5, -3), (0, 3)),
+                ("c", (1, 0), (-1, 3), (3, 6)),
+                ("b", (5, -2), (5, 5), (9, 1)),
+            ],
+            sections
+        );
+
+        // each section spirals out from its own centre
+        assert_eq!("a0", grid.item_at(-4, -1).unwrap().value);
+        assert_eq!("a2", grid.item_at(-4, 0).unwrap().value);
+        assert_eq!("a1", grid.item_at(-3, -1).unwrap().value);
+        assert_eq!("c1", grid.item_at(1, 1).unwrap().value);
+        assert_eq!("b0", grid.item_at(5, -2).unwrap().value);
+        // items remember where they were in the choices, which grouping has reordered
+        assert_eq!(3, grid.item_at(5, -2).unwrap().index);
+        assert_eq!(4, grid.item_at(-3, -1).unwrap().index);
+        // the gaps between sections, and the title and bottom rows, are empty
+        assert!(grid.item_at(-2, -1).is_none());
+        assert!(grid.item_at(4, 0).is_none());
+        assert!(grid.item_at(-4, -3).is_none());
+        assert!(grid.item_at(1, 3).is_none());
+        assert!(grid.item_at(i8::MIN, i8::MAX).is_none());
+
+        for (index, item) in grid.items_iter().enumerate() {
+            assert_eq!(Some(index), grid.items.index_at(&item.position));
+            assert_eq!(Some(item.position.clone()), grid.items.position_of(index));
+        }
+        assert_eq!(None, grid.items.position_of(choices.len()));
+    }
+
+    #[test]
+    fn test_ungrouped_choices_share_a_section() {
+        let mut choices = grouped(&[("a", 1)]);
+        choices.push(Choice::new("loose".to_string()));
+        let grid = Grid::new(&choices).unwrap();
+
+        let titles: Vec<_> = grid
+            .items
+            .sections()
+            .iter()
+            .map(|s| s.title.as_deref())
+            .collect();
+        assert_eq!(vec![Some("a"), None], titles);
+
+        // without any groups there are no titles, and no rows for them
+        let grid = Grid::new(&[Choice::new("a".to_string())]).unwrap();
+        assert_eq!(1, grid.height);
+        assert_eq!(None, grid.title_row());
+    }
+
+    #[test]
+    fn test_too_many_groups() {
+        // one item per group needs a column for each, and one between each
+        let names: Vec<_> = (0..max_items()).map(|i| i.to_string()).collect();
+        let groups: Vec<_> = names.iter().map(|n| (n.as_str(), 1)).collect();
+        let choices = grouped(&groups);
+
+        assert!(!fits(&choices));
+        assert!(Grid::new(&choices).is_err());
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let position = self.positions.next();
-        position.and_then(|p| self.grid.item_at(p.x, p.y))
+        let fitting = &choices[..MAX_WIDTH / 2 + 1];
+        assert!(fits(fitting));
+        assert_eq!(MAX_WIDTH, Grid::new(fitting).unwrap().width);
     }
 }
@@ -0,0 +1,303 @@
+// a record of past selections, used to put frequently and recently chosen
+// items closest to the centre of the grid.
+//
+// each history key has its own file in $XDG_STATE_HOME/grid-select/history/.
+// the file has one line per item: "<score>\t<last used>\t<value>", where the
+// last used time is in seconds since the unix epoch.
+//
+// an item's score goes up by one each time it's chosen, and halves every
+// HALF_LIFE seconds after that.
+
+use crate::file;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HISTORY_DIR: &str = "history";
+
+// two weeks
+const HALF_LIFE: f64 = 14. * 24. * 60. * 60.;
+
+// entries whose score has decayed below this are forgotten.
+const MIN_SCORE: f64 = 0.05;
+
+// the most entries that we'll keep for each history key.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+struct Entry {
+    score: f64,
+    last_used: u64,
+}
+
+impl Entry {
+    // the score, decayed to the time `now`.
+    fn score_at(&self, now: u64) -> f64 {
+        let elapsed = now.saturating_sub(self.last_used) as f64;
+        self.score * 0.5_f64.powf(elapsed / HALF_LIFE)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct History {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// history keys become file names, so they're restricted to a safe set of characters.
+fn validate_key(key: &str) -> Result<()> {
+    anyhow::ensure!(
+        !key.is_empty()
+            && !key.starts_with('.')
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.')),
+        "invalid history name {key:?}, expected letters, numbers, '-', '_' or '.'"
+    );
+    Ok(())
+}
+
+fn parse_line(line: &str) -> Option<(String, Entry)> {
+    let mut fields = line.splitn(3, '\t');
+    let score = fields.next()?.parse::<f64>().ok()?;
+    let last_used = fields.next()?.parse().ok()?;
+    let value = fields.next()?;
+
+    (score.is_finite() && score > 0.).then(|| (value.to_string(), Entry { score, last_used }))
+}
+
+impl History {
+    // load the history for `key`. a missing file is an empty history.
+    pub fn load(key: &str) -> Result<History> {
+        validate_key(key)?;
+
+        let path = xdg::BaseDirectories::with_prefix(crate::prog_name!())
+            .place_state_file(format!("{HISTORY_DIR}/{key}"))
+            .context("failed to create the history directory")?;
+
+        History::load_from(path)
+    }
+
+    fn load_from(path: PathBuf) -> Result<History> {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+
+        let mut entries = HashMap::new();
+        for line in contents.lines().filter(|l| !l.is_empty()) {
+            match parse_line(line) {
+                Some((value, entry)) => {
+                    entries.insert(value, entry);
+                }
+                None => log::warn!("ignoring invalid history line {line:?}"),
+            }
+        }
+
+        Ok(History { path, entries })
+    }
+
+    // note that `value` was chosen at time `now`.
+    pub fn record(&mut self, value: &str, now: u64) {
+        let score = self.score(value, now) + 1.;
+        self.entries.insert(
+            value.to_string(),
+            Entry {
+                score,
+                last_used: now,
+            },
+        );
+    }
+
+    // the item's frecency at time `now`. items that have never been chosen score 0.
+    pub fn score(&self, value: &str, now: u64) -> f64 {
+        self.entries.get(value).map_or(0., |e| e.score_at(now))
+    }
+
+    // forget entries that have decayed away, and the lowest scoring entries
+    // if there are too many.
+    fn prune(&mut self, now: u64) {
+        self.entries.retain(|_, e| e.score_at(now) >= MIN_SCORE);
+
+        if self.entries.len() > MAX_ENTRIES {
+            let mut scores: Vec<f64> = self.entries.values().map(|e| e.score_at(now)).collect();
+            scores.sort_by(|a, b| b.total_cmp(a));
+            let cutoff = scores[MAX_ENTRIES - 1];
+
+            self.entries.retain(|_, e| e.score_at(now) >= cutoff);
+            // ties at the cutoff could leave us over the limit
+            while self.entries.len() > MAX_ENTRIES {
+                let lowest = self
+                    .entries
+                    .iter()
+                    .min_by(|(_, a), (_, b)| a.last_used.cmp(&b.last_used))
+                    .map(|(v, _)| v.clone())
+                    .unwrap();
+                self.entries.remove(&lowest);
+            }
+        }
+    }
+
+    // prune the history and write it out.
+    //
+    // the file is replaced atomically, so a crash (or another grid-select
+    // writing the same history) can't leave it half written.
+    pub fn save(&mut self, now: u64) -> Result<()> {
+        self.prune(now);
+
+        // values can't contain newlines (they come from lines of input), but
+        // check anyway so that a bad value can't corrupt the file.
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(v, _)| !v.contains('\n'))
+            .collect();
+        entries.sort_by_key(|(v, _)| *v);
+
+        let mut contents = String::new();
+        for (value, entry) in entries {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\n",
+                entry.score, entry.last_used, value
+            ));
+        }
+
+        file::write_atomic(&self.path, contents.as_bytes())
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "grid-select-test-history-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut history = History::default();
+        history.record("firefox", 0);
+
+        assert!(close(1., history.score("firefox", 0)));
+        assert!(close(0.5, history.score("firefox", 14 * DAY)));
+        assert!(close(0.25, history.score("firefox", 28 * DAY)));
+        assert_eq!(0., history.score("kitty", 0));
+    }
+
+    #[test]
+    fn test_record_accumulates() {
+        let mut history = History::default();
+        history.record("firefox", 0);
+        history.record("firefox", 14 * DAY);
+
+        assert!(close(1.5, history.score("firefox", 14 * DAY)));
+    }
+
+    #[test]
+    fn test_rank() {
+        let mut history = History::default();
+        // chosen often, but long ago
+        for _ in 0..4 {
+            history.record("a", 0);
+        }
+        // chosen once, just now
+        history.record("b", 60 * DAY);
+        // chosen twice recently
+        history.record("c", 59 * DAY);
+        history.record("c", 60 * DAY);
+
+        let now = 60 * DAY;
+        assert!(history.score("c", now) > history.score("b", now));
+        assert!(history.score("b", now) > history.score("a", now));
+        assert!(history.score("a", now) > history.score("x", now));
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut history = History::default();
+        history.record("old", 0);
+        history.record("new", 100 * DAY);
+
+        history.prune(100 * DAY);
+
+        assert!(!history.entries.contains_key("old"));
+        assert!(history.entries.contains_key("new"));
+    }
+
+    #[test]
+    fn test_prune_limit() {
+        let mut history = History::default();
+        for i in 0..MAX_ENTRIES as u64 + 10 {
+            history.record(&i.to_string(), i);
+        }
+
+        history.prune(MAX_ENTRIES as u64 + 10);
+
+        assert_eq!(MAX_ENTRIES, history.entries.len());
+        // the oldest entries are the ones that go
+        assert!(!history.entries.contains_key("0"));
+        assert!(history.entries.contains_key("10"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path("round-trip");
+
+        let mut history = History::load_from(path.clone()).unwrap();
+        assert!(history.entries.is_empty());
+
+        history.record("firefox", 100);
+        history.record("with\ttab", 200);
+        history.save(200).unwrap();
+
+        let loaded = History::load_from(path.clone()).unwrap();
+        assert_eq!(history.entries, loaded.entries);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_load_skips_invalid_lines() {
+        let path = temp_path("invalid");
+        std::fs::write(&path, "1.5\t100\tfirefox\nnonsense\nNaN\t100\tkitty\n").unwrap();
+
+        let history = History::load_from(path.clone()).unwrap();
+        assert_eq!(vec!["firefox"], history.entries.keys().collect::<Vec<_>>());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("apps").is_ok());
+        assert!(validate_key("hyprland-workspaces_2").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("../config").is_err());
+        assert!(validate_key("a/b").is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// where an item's icon goes relative to its text.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IconPosition {
+    #[default]
+    Left,
+    Top,
+}
+
+// an icon is either a path to an image, or the name of an icon in the user's icon theme.
+fn is_path(spec: &str) -> bool {
+    spec.contains('/') || spec.ends_with(".png") || spec.ends_with(".svg")
+}
+
+// find the image file for an icon.
+//
+// names are looked up in the freedesktop icon theme (falling back to hicolor),
+// searching $XDG_DATA_HOME/icons and $XDG_DATA_DIRS/icons.
+//
+// files that we can't draw (e.g. the xpm icons that some themes still ship)
+// are skipped here, rather than failing each time they're drawn.
+pub fn resolve(spec: &str, theme: Option<&str>, size: u16) -> Option<PathBuf> {
+    let path = if is_path(spec) {
+        Some(PathBuf::from(spec)).filter(|p| p.is_file())
+    } else {
+        let lookup = freedesktop_icons::lookup(spec).with_size(size).with_cache();
+        match theme {
+            Some(theme) => lookup.with_theme(theme).find(),
+            None => lookup.find(),
+        }
+    };
+
+    path.filter(|path| {
+        let supported = Format::of(path).is_some();
+        if !supported {
+            log::debug!("skipping icon {}: unsupported format", path.display());
+        }
+        supported
+    })
+}
+
+// the kinds of image that we can draw.
+#[derive(Debug, PartialEq)]
+enum Format {
+    Png,
+    // plain or gzipped (.svgz)
+    Svg,
+}
+
+impl Format {
+    // what a file holds, judging by its first few bytes rather than its name,
+    // which can't always be trusted.
+    fn of(path: &Path) -> Option<Format> {
+        let mut start = Vec::with_capacity(64);
+        std::fs::File::open(path)
+            .and_then(|f| f.take(64).read_to_end(&mut start))
+            .ok()?;
+
+        let text = start
+            .strip_prefix("\u{feff}".as_bytes())
+            .unwrap_or(&start)
+            .trim_ascii_start();
+        if start.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Format::Png)
+        } else if start.starts_with(&[0x1f, 0x8b]) || text.starts_with(b"<") {
+            Some(Format::Svg)
+        } else {
+            None
+        }
+    }
+}
+
+// a decoded icon, in the premultiplied ARGB format that raqote draws.
+pub struct Icon {
+    width: i32,
+    height: i32,
+    data: Vec<u32>,
+}
+
+impl Icon {
+    pub fn as_image(&self) -> raqote::Image<'_> {
+        raqote::Image {
+            width: self.width,
+            height: self.height,
+            data: &self.data,
+        }
+    }
+
+    fn from_premultiplied_rgba(width: u32, height: u32, rgba: &[u8]) -> Icon {
+        let data = rgba
+            .chunks_exact(4)
+            .map(|p| u32::from_be_bytes([p[3], p[0], p[1], p[2]]))
+            .collect();
+
+        Icon {
+            width: width as i32,
+            height: height as i32,
+            data,
+        }
+    }
+}
+
+fn premultiply(c: u8, a: u8) -> u8 {
+    ((c as u16 * a as u16 + 127) / 255) as u8
+}
+
+fn load_png(path: &Path) -> Result<Icon> {
+    let file = std::fs::File::open(path)?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let pixels = &buf[..info.buffer_size()];
+
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let a = p[3];
+                [
+                    premultiply(p[0], a),
+                    premultiply(p[1], a),
+                    premultiply(p[2], a),
+                    a,
+                ]
+            })
+            .collect(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 0xff])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| {
+                let v = premultiply(p[0], p[1]);
+                [v, v, v, p[1]]
+            })
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|v| [*v, *v, *v, 0xff]).collect(),
+        png::ColorType::Indexed => anyhow::bail!("indexed png was not expanded"),
+    };
+
+    Ok(Icon::from_premultiplied_rgba(
+        info.width,
+        info.height,
+        &rgba,
+    ))
+}
+
+// rasterise the svg so that it fits in a `size` pixel square.
+fn load_svg(path: &Path, size: u32) -> Result<Icon> {
+    let data = std::fs::read(path)?;
+    let tree = resvg::usvg::Tree::from_data(&data, &resvg::usvg::Options::default())?;
+
+    let tree_size = tree.size();
+    let scale = size as f32 / tree_size.width().max(tree_size.height());
+    let width = ((tree_size.width() * scale).ceil() as u32).max(1);
+    let height = ((tree_size.height() * scale).ceil() as u32).max(1);
+
+    let mut pixmap =
+        resvg::tiny_skia::Pixmap::new(width, height).context("invalid svg dimensions")?;
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia pixmaps are already premultiplied
+    Ok(Icon::from_premultiplied_rgba(width, height, pixmap.data()))
+}
+
+fn load(path: &Path, size: u32) -> Result<Icon> {
+    match Format::of(path) {
+        Some(Format::Svg) => load_svg(path, size),
+        Some(Format::Png) => load_png(path),
+        None => anyhow::bail!("unsupported image format"),
+    }
+}
+
+// decoded icons, keyed by file and the pixel size they were loaded for.
+//
+// svgs are rasterised at exactly the size they'll be drawn, so they need to be
+// reloaded when the scale changes. pngs are scaled when they're drawn.
+#[derive(Default)]
+pub struct Cache {
+    icons: HashMap<(PathBuf, u32), Option<Icon>>,
+}
+
+impl Cache {
+    pub fn get(&mut self, path: &Path, size: u32) -> Option<&Icon> {
+        self.icons
+            .entry((path.to_path_buf(), size))
+            .or_insert_with(|| match load(path, size) {
+                Ok(icon) => Some(icon),
+                Err(e) => {
+                    log::warn!("failed to load icon {}: {e:#}", path.display());
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    // forget icons that were loaded for a different scale.
+    pub fn clear(&mut self) {
+        self.icons.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_path() {
+        assert!(is_path("/usr/share/pixmaps/firefox.png"));
+        assert!(is_path("icons/terminal.svg"));
+        assert!(is_path("firefox.png"));
+        assert!(!is_path("firefox"));
+        assert!(!is_path("org.gnome.Terminal"));
+    }
+
+    #[test]
+    fn test_load_svg() {
+        let path =
+            std::env::temp_dir().join(format!("grid-select-test-icon-{}.svg", std::process::id()));
+        std::fs::write(
+            &path,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="8">
+                <rect width="16" height="8" fill="#ff0000"/>
+            </svg>"##,
+        )
+        .unwrap();
+
+        let icon = load(&path, 32).unwrap();
+        assert_eq!((32, 16), (icon.width, icon.height));
+        assert_eq!(0xffff0000, icon.data[0]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "grid-select-test-icon-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    // a 2x1 png: opaque red, then half-transparent white.
+    fn png() -> Vec<u8> {
+        let mut data = vec![];
+        let mut encoder = png::Encoder::new(&mut data, 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[0xff, 0, 0, 0xff, 0xff, 0xff, 0xff, 0x80])
+            .unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn test_load_png() {
+        let path = temp_file("red.png", &png());
+
+        let icon = load(&path, 32).unwrap();
+        assert_eq!((2, 1), (icon.width, icon.height));
+        // premultiplied ARGB
+        assert_eq!(vec![0xffff0000, 0x80808080], icon.data);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_format() {
+        // the contents decide, not the name
+        let misnamed = temp_file("misnamed.svg", &png());
+        assert_eq!(Some(Format::Png), Format::of(&misnamed));
+        assert!(load(&misnamed, 32).is_ok());
+
+        let xpm = temp_file("icon.xpm", b"/* XPM */\nstatic char *icon[] = {};\n");
+        assert_eq!(None, Format::of(&xpm));
+        assert!(load(&xpm, 32).is_err());
+        assert_eq!(None, resolve(xpm.to_str().unwrap(), None, 32));
+
+        let svg = temp_file("icon.svg", b"\n  <?xml version=\"1.0\"?><svg/>");
+        assert_eq!(Some(Format::Svg), Format::of(&svg));
+
+        for path in [misnamed, xpm, svg] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
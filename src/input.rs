@@ -0,0 +1,503 @@
+// the choices that will be shown, as they arrive.
+//
+// stdin is read in the background, so that the window can be shown before a
+// slow producer has finished writing. each choice is ranked into place as it's
+// read, and only the ones that fit in the grid are kept.
+
+use crate::grid::{self, Choice, Preselect};
+use crate::history::History;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use smithay_client_toolkit::reexports::calloop::channel;
+use std::io::BufRead;
+
+// how each line of input describes a choice.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Format {
+    // separates the fields of each line. without one, the line is the value.
+    pub delimiter: Option<String>,
+    // the last field is an icon
+    pub icons: bool,
+    // the first field is a group
+    pub groups: bool,
+    // each line is a choice in JSON, which may have children. the other
+    // options are ignored.
+    pub json: bool,
+}
+
+impl Format {
+    // lines are "[group<d>]value<d>display[<d>icon]", depending on the options.
+    pub fn parse(&self, line: String) -> Result<Choice> {
+        if self.json {
+            return serde_json::from_str(&line).with_context(|| format!("invalid choice {line:?}"));
+        }
+
+        let delimiter = self.delimiter.as_deref();
+        let (group, line) = match delimiter.filter(|_| self.groups) {
+            Some(d) => match line.split_once(d) {
+                Some((group, rest)) => (Some(group.to_string()), rest.to_string()),
+                None => (None, line),
+            },
+            None => (None, line),
+        };
+
+        let Some((value, rest)) = delimiter.and_then(|d| line.split_once(d)) else {
+            return Ok(Choice {
+                group,
+                ..Choice::new(line)
+            });
+        };
+
+        let (display, icon) = match delimiter
+            .filter(|_| self.icons)
+            .and_then(|d| rest.rsplit_once(d))
+        {
+            Some((display, icon)) => (display, Some(icon.to_string())),
+            None => (rest, None),
+        };
+
+        Ok(Choice {
+            display: Some(display.to_string()),
+            icon: icon.filter(|i| !i.is_empty()),
+            group,
+            ..Choice::new(value.to_string())
+        })
+    }
+}
+
+// how far a line is indented, and the rest of it.
+pub fn indentation(line: &str) -> (usize, String) {
+    let rest = line.trim_start();
+    (line.len() - rest.len(), rest.to_string())
+}
+
+// the choices printed by a submenu's command, one per line.
+pub fn command_choices(command: &str, format: &Format) -> Result<Vec<Choice>> {
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .with_context(|| format!("failed to run {command:?}"))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "{command:?} failed: {}",
+        output.status
+    );
+
+    let choices: Vec<_> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| format.parse(l.to_string()))
+        .collect::<Result<_>>()?;
+    anyhow::ensure!(!choices.is_empty(), "{command:?} didn't print any choices");
+
+    Ok(choices)
+}
+
+// run a submenu's command in the background, so that a slow one doesn't hold
+// up the window. its choices arrive on the returned channel once it's done.
+pub fn read_command(command: &str, format: &Format) -> channel::Channel<Result<Vec<Choice>>> {
+    let (sender, channel) = channel::channel();
+    let command = command.to_string();
+    let format = format.clone();
+    std::thread::spawn(move || {
+        // the window no longer wants the choices if the channel's gone
+        let _ = sender.send(command_choices(&command, &format));
+    });
+    channel
+}
+
+pub struct Choices {
+    // the choices that fit in the grid, in the order that they'll be placed
+    shown: Vec<Choice>,
+    // how many choices we've been given
+    received: usize,
+    limit: usize,
+
+    history: Option<History>,
+    now: u64,
+
+    preselect: Option<Preselect>,
+    preselected: Option<String>,
+
+    // for nested input: the indentation of the last choice at each level,
+    // outermost first, and the value of the last choice at the top level.
+    indents: Vec<usize>,
+    top: Option<String>,
+}
+
+impl Choices {
+    pub fn new(history: Option<History>, now: u64, preselect: Option<Preselect>) -> Choices {
+        Choices {
+            shown: vec![],
+            received: 0,
+            limit: grid::max_items(),
+            history,
+            now,
+            preselect,
+            preselected: None,
+            indents: vec![],
+            top: None,
+        }
+    }
+
+    fn score(&self, choice: &Choice) -> f64 {
+        self.history
+            .as_ref()
+            .map_or(0., |h| h.score(&choice.value, self.now))
+    }
+
+    // add a choice. returns true if the shown choices changed.
+    pub fn push(&mut self, mut choice: Choice) -> bool {
+        if self.preselected.is_none()
+            && self
+                .preselect
+                .as_ref()
+                .is_some_and(|p| p.matches(self.received, &choice))
+        {
+            self.preselected = Some(choice.value.clone());
+        }
+        choice.input_index = Some(self.received);
+        self.received += 1;
+
+        // after everything that scores at least as highly, so that choices with
+        // equal scores keep their order.
+        let score = self.score(&choice);
+        let index = self
+            .shown
+            .iter()
+            .position(|c| self.score(c) < score)
+            .unwrap_or(self.shown.len());
+
+        if index >= self.limit {
+            return false;
+        }
+
+        // a choice that would start another group may not have room for it
+        self.shown.insert(index, choice);
+        if !grid::fits(&self.shown[..self.shown.len().min(self.limit)]) {
+            self.shown.remove(index);
+            return false;
+        }
+        self.shown.truncate(self.limit);
+        true
+    }
+
+    // add a choice that's indented by `indent`, making it a child of the last
+    // choice that's indented less. returns true if the shown choices changed.
+    pub fn push_indented(&mut self, indent: usize, choice: Choice) -> bool {
+        while self.indents.last().is_some_and(|i| *i >= indent) {
+            self.indents.pop();
+        }
+        let depth = self.indents.len();
+        self.indents.push(indent);
+
+        if depth == 0 {
+            self.top = Some(choice.value.clone());
+            return self.push(choice);
+        }
+
+        // children of a choice that didn't make the cut are dropped with it
+        let Some(mut parent) = self
+            .top
+            .as_ref()
+            .and_then(|top| self.shown.iter_mut().rfind(|c| &c.value == top))
+        else {
+            return false;
+        };
+        for _ in 1..depth {
+            match parent.children.last_mut() {
+                Some(child) => parent = child,
+                None => return false,
+            }
+        }
+
+        parent.children.push(choice);
+        true
+    }
+
+    pub fn shown(&self) -> &[Choice] {
+        &self.shown
+    }
+
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    // the value of the choice that the cursor should start on, once it's arrived.
+    pub fn preselected(&self) -> Option<&str> {
+        self.preselected.as_deref()
+    }
+
+    // print warnings about choices that were asked for but can't be shown.
+    pub fn warn_missing(&self) {
+        if self.received > self.shown.len() {
+            eprintln!(
+                "found {} items, only the first {} will be shown.",
+                self.received,
+                self.shown.len()
+            );
+        }
+
+        match &self.preselected {
+            None if self.preselect.is_some() => {
+                eprintln!("the item to preselect wasn't found, starting in the centre.")
+            }
+            Some(value) if !self.shown.iter().any(|c| &c.value == value) => {
+                eprintln!("the item to preselect isn't shown, starting in the centre.")
+            }
+            _ => {}
+        }
+    }
+}
+
+// lines of input as they arrive, followed by None once they've all been read.
+//
+// the end is a message of its own because calloop's Closed event can be missed:
+// a sender wakes the event loop as it's dropped, just before the channel closes.
+pub type Lines = channel::Channel<Option<String>>;
+
+// send each line to the event loop, and then the end.
+pub fn forward_lines(reader: impl BufRead, sender: channel::Sender<Option<String>>) {
+    for line in reader.lines() {
+        match line {
+            Ok(line) => {
+                if sender.send(Some(line)).is_err() {
+                    // the event loop has gone away
+                    return;
+                }
+            }
+            Err(e) => {
+                eprintln!("failed to read line: {e}");
+                break;
+            }
+        }
+    }
+    let _ = sender.send(None);
+}
+
+// read lines in a background thread, delivering them to the event loop as they arrive.
+pub fn read_lines(reader: impl BufRead + Send + 'static) -> Lines {
+    let (sender, channel) = channel::channel();
+    std::thread::spawn(move || forward_lines(reader, sender));
+    channel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(choices: &Choices) -> Vec<&str> {
+        choices.shown().iter().map(|c| c.value.as_str()).collect()
+    }
+
+    fn push_all(choices: &mut Choices, values: &[&str]) {
+        for v in values {
+            choices.push(Choice::new(v.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut choices = Choices::new(None, 0, None);
+        choices.limit = 3;
+
+        assert!(choices.push(Choice::new("a".into())));
+        push_all(&mut choices, &["b", "c"]);
+        assert!(!choices.push(Choice::new("d".into())));
+
+        assert_eq!(vec!["a", "b", "c"], values(&choices));
+        assert_eq!(4, choices.received());
+    }
+
+    #[test]
+    fn test_ranked() {
+        let mut history = History::default();
+        history.record("d", 0);
+        history.record("b", 0);
+        history.record("b", 0);
+
+        let mut choices = Choices::new(Some(history), 0, None);
+        choices.limit = 3;
+        push_all(&mut choices, &["a", "b", "c", "d", "e"]);
+
+        // "d" arrives after the grid is full, but still makes the cut
+        assert_eq!(vec!["b", "d", "a"], values(&choices));
+    }
+
+    #[test]
+    fn test_too_many_groups() {
+        let mut choices = Choices::new(None, 0, None);
+        for i in 0..grid::max_items() {
+            choices.push(Choice {
+                group: Some(i.to_string()),
+                ..Choice::new(i.to_string())
+            });
+        }
+
+        // the groups that didn't fit were left out
+        let shown = choices.shown().len();
+        assert!(shown < grid::max_items());
+        assert!(grid::Grid::new(choices.shown()).is_ok());
+        assert!(!choices.push(Choice {
+            group: Some("another".to_string()),
+            ..Choice::new("more".to_string())
+        }));
+        assert_eq!(shown, choices.shown().len());
+    }
+
+    #[test]
+    fn test_input_index() {
+        let mut history = History::default();
+        history.record("c", 0);
+
+        let mut choices = Choices::new(Some(history), 0, Some(Preselect::Index(2)));
+        push_all(&mut choices, &["a", "b", "c"]);
+        assert_eq!(vec!["c", "a", "b"], values(&choices));
+        assert_eq!(Some("c"), choices.preselected());
+
+        // the chosen item is still described by where it was in the input
+        let grid = grid::Grid::new(choices.shown()).unwrap();
+        let mut state = crate::state::State::new(grid, choices.preselected());
+        state.select();
+        let selected = state.selected.unwrap();
+        assert_eq!("c", selected.value);
+        assert_eq!(2, selected.index);
+    }
+
+    #[test]
+    fn test_preselect() {
+        let mut choices = Choices::new(None, 0, Some(Preselect::Index(2)));
+        push_all(&mut choices, &["a", "b"]);
+        assert_eq!(None, choices.preselected());
+
+        push_all(&mut choices, &["c", "d"]);
+        assert_eq!(Some("c"), choices.preselected());
+    }
+
+    fn format(delimiter: Option<&str>, icons: bool, groups: bool) -> Format {
+        Format {
+            delimiter: delimiter.map(str::to_string),
+            icons,
+            groups,
+            json: false,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let choice = format(None, false, false).parse("a,b".into()).unwrap();
+        assert_eq!(Choice::new("a,b".into()), choice);
+
+        let choice = format(Some(","), true, true)
+            .parse("web,firefox,Fire,fox,firefox-icon".into())
+            .unwrap();
+        assert_eq!("firefox", choice.value);
+        assert_eq!(Some("Fire,fox"), choice.display.as_deref());
+        assert_eq!(Some("firefox-icon"), choice.icon.as_deref());
+        assert_eq!(Some("web"), choice.group.as_deref());
+
+        let json = Format {
+            json: true,
+            ..Format::default()
+        };
+        let choice = json
+            .parse(r#"{"value": "a", "children": [{"value": "b"}], "icon": null}"#.into())
+            .unwrap();
+        assert_eq!(vec![Choice::new("b".into())], choice.children);
+        assert!(json.parse("a".into()).is_err());
+    }
+
+    #[test]
+    fn test_push_indented() {
+        let mut choices = Choices::new(None, 0, None);
+        for line in ["a", "  b", "    c", "  d", "e", "\tf"] {
+            let (indent, value) = indentation(line);
+            assert!(choices.push_indented(indent, Choice::new(value)));
+        }
+
+        assert_eq!(vec!["a", "e"], values(&choices));
+        let a = &choices.shown()[0];
+        let children: Vec<_> = a.children.iter().map(|c| c.value.as_str()).collect();
+        assert_eq!(vec!["b", "d"], children);
+        assert_eq!(vec![Choice::new("c".into())], a.children[0].children);
+        assert_eq!(vec![Choice::new("f".into())], choices.shown()[1].children);
+    }
+
+    #[test]
+    fn test_command_choices() {
+        let choices =
+            command_choices("printf 'a,A\\n\\nb,B\\n'", &format(Some(","), false, false)).unwrap();
+        let values: Vec<_> = choices.iter().map(|c| c.value.as_str()).collect();
+        assert_eq!(vec!["a", "b"], values);
+
+        assert!(command_choices("exit 1", &Format::default()).is_err());
+        assert!(command_choices("true", &Format::default()).is_err());
+    }
+
+    #[test]
+    fn test_read_lines() {
+        let mut event_loop =
+            smithay_client_toolkit::reexports::calloop::EventLoop::<Vec<String>>::try_new()
+                .unwrap();
+        let channel = read_lines(std::io::Cursor::new("one\ntwo\nthree"));
+
+        let closed = std::rc::Rc::new(std::cell::Cell::new(false));
+        event_loop
+            .handle()
+            .insert_source(channel, {
+                let closed = closed.clone();
+                move |event, _, lines| match event {
+                    channel::Event::Msg(Some(line)) => lines.push(line),
+                    channel::Event::Msg(None) => closed.set(true),
+                    channel::Event::Closed => {}
+                }
+            })
+            .unwrap();
+
+        let mut lines = vec![];
+        while !closed.get() {
+            event_loop
+                .dispatch(std::time::Duration::from_secs(1), &mut lines)
+                .unwrap();
+        }
+
+        assert_eq!(vec!["one", "two", "three"], lines);
+    }
+
+    #[test]
+    fn test_read_command() {
+        let mut event_loop = smithay_client_toolkit::reexports::calloop::EventLoop::<
+            Option<Result<Vec<Choice>>>,
+        >::try_new()
+        .unwrap();
+        event_loop
+            .handle()
+            .insert_source(
+                read_command("sleep 0.1; echo a", &Format::default()),
+                |event, _, choices| {
+                    if let channel::Event::Msg(c) = event {
+                        *choices = Some(c);
+                    }
+                },
+            )
+            .unwrap();
+
+        // nothing's held up while the command runs
+        let mut choices = None;
+        event_loop
+            .dispatch(std::time::Duration::ZERO, &mut choices)
+            .unwrap();
+        assert!(choices.is_none());
+
+        while choices.is_none() {
+            event_loop
+                .dispatch(std::time::Duration::from_secs(1), &mut choices)
+                .unwrap();
+        }
+        assert_eq!(vec![Choice::new("a".into())], choices.unwrap().unwrap());
+    }
+}
@@ -39,6 +39,22 @@ pub fn grid_position_to_screen(
     (ScreenPosition { x, y }, Space { width, height })
 }
 
+// grow a rectangle by `by` on every side, or shrink it if `by` is negative.
+// the rectangle won't shrink below zero size.
+pub fn inflate(position: &ScreenPosition, space: &Space, by: f32) -> (ScreenPosition, Space) {
+    let by = by.max(-space.width / 2.).max(-space.height / 2.);
+    (
+        ScreenPosition {
+            x: position.x - by,
+            y: position.y - by,
+        },
+        Space {
+            width: space.width + 2. * by,
+            height: space.height + 2. * by,
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,4 +121,47 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_inflate() {
+        let position = ScreenPosition { x: 10., y: 20. };
+        let space = Space {
+            width: 100.,
+            height: 50.,
+        };
+
+        assert_eq!(
+            inflate(&position, &space, 2.),
+            (
+                ScreenPosition { x: 8., y: 18. },
+                Space {
+                    width: 104.,
+                    height: 54.
+                }
+            )
+        );
+
+        assert_eq!(
+            inflate(&position, &space, -5.),
+            (
+                ScreenPosition { x: 15., y: 25. },
+                Space {
+                    width: 90.,
+                    height: 40.
+                }
+            )
+        );
+
+        // can't shrink past nothing
+        assert_eq!(
+            inflate(&position, &space, -30.),
+            (
+                ScreenPosition { x: 35., y: 45. },
+                Space {
+                    width: 50.,
+                    height: 0.
+                }
+            )
+        );
+    }
 }
@@ -1,14 +1,31 @@
+pub mod clipboard;
 pub mod config;
+pub mod daemon;
+pub mod drun;
+pub mod exec;
+pub mod fonts;
 pub mod grid;
+pub mod history;
+pub mod input;
 pub mod layout;
 pub mod render;
+pub mod script;
 pub mod spiral;
 pub mod state;
+pub mod theme;
+pub mod toplevel;
 pub mod window;
 
+mod accessibility;
+mod animation;
 mod colour;
+mod file;
+mod icon;
 mod text;
 
+#[cfg(test)]
+mod test_compositor;
+
 #[macro_export]
 macro_rules! prog_name {
     () => {
@@ -1,7 +1,37 @@
 use argh::FromArgs;
-use grid_select::{config, window};
-use std::io::{self, BufRead};
+use grid_select::{
+    clipboard, config, daemon, drun, exec, grid, history, input, script, state, theme, toplevel,
+    window,
+};
+use smithay_client_toolkit::reexports::calloop::{LoopHandle, RegistrationToken, channel};
+use std::io;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+// where the items come from.
+enum Mode {
+    // lines read from stdin
+    Stdin,
+    // installed applications
+    Drun,
+    // open windows
+    Windows,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdin" => Ok(Mode::Stdin),
+            "drun" => Ok(Mode::Drun),
+            "windows" => Ok(Mode::Windows),
+            _ => Err(format!(
+                "unknown mode {s:?}, expected stdin, drun or windows"
+            )),
+        }
+    }
+}
 
 /// Graphical picker like XMonad's GridSelect.
 #[derive(FromArgs)]
@@ -15,44 +45,488 @@ struct Args {
     /// "value" will be written to stdout when an item is chosen.
     #[argh(option, short = 'd')]
     delimiter: Option<String>,
+
+    /// each line has an icon. requires a delimiter; lines should be in the form
+    /// "value<delimiter>display<delimiter>icon". "icon" is either the path to a
+    /// PNG or SVG file, or the name of an icon in your icon theme.
+    #[argh(switch, short = 'i')]
+    icons: bool,
+
+    /// each line starts with a group. requires a delimiter; lines should be in
+    /// the form "group<delimiter>value<delimiter>display". items in the same
+    /// group are shown together, under the group's name.
+    #[argh(switch, short = 'g')]
+    groups: bool,
+
+    /// each line is a choice in JSON, e.g. {"value": "v", "display": "d",
+    /// "icon": "i", "group": "g"}. a choice with "children", a list of choices,
+    /// or "command", a shell command that prints one choice per line, opens
+    /// a submenu.
+    #[argh(switch)]
+    json: bool,
+
+    /// indented lines are the children of the line above them that's indented
+    /// less, which opens them in a submenu.
+    #[argh(switch)]
+    tree: bool,
+
+    /// get the items from a script, which is run again with the chosen value
+    /// on its stdin each time an item is chosen. if it prints more items, they
+    /// are shown next; if it prints nothing, we exit. the items are read in the
+    /// same way as stdin (see -d, -i, -g, --json and --tree).
+    #[argh(option)]
+    script: Option<PathBuf>,
+
+    /// run this shell command when an item is chosen, instead of printing its
+    /// value. "{value}", "{display}" and "{index}" (its place in the input,
+    /// counting from 0) are replaced by the chosen item's, quoted for the shell.
+    #[argh(option)]
+    exec: Option<String>,
+
+    /// copy the chosen value to the clipboard, instead of printing it. this
+    /// needs a compositor that supports wlr-data-control.
+    #[argh(switch)]
+    clipboard: bool,
+
+    /// serve a value read from stdin on the clipboard (used by --clipboard).
+    #[argh(switch, hidden_help)]
+    serve_clipboard: bool,
+
+    /// where the items come from. "stdin" (the default) reads them from stdin;
+    /// "drun" lists installed applications and launches the one that's chosen;
+    /// "windows" lists open windows and focuses the one that's chosen.
+    #[argh(option, default = "Mode::Stdin")]
+    mode: Mode,
+
+    /// in drun mode, print the chosen application's command instead of running it.
+    #[argh(switch)]
+    dry_run: bool,
+
+    /// name of the colour theme to use, overriding the config file.
+    #[argh(option)]
+    theme: Option<String>,
+
+    /// start with the cursor on the item with this value.
+    #[argh(option)]
+    select: Option<String>,
+
+    /// start with the cursor on the nth item (counting from 0, in the order
+    /// they were given).
+    #[argh(option)]
+    select_index: Option<usize>,
+
+    /// start with the cursor on the item that's shown with this text.
+    #[argh(option)]
+    select_display: Option<String>,
+
+    /// remember the chosen items under this name, and put the most frequently
+    /// and recently chosen items closest to the centre.
+    #[argh(option)]
+    history: Option<String>,
+
+    /// run in the background, showing pickers for clients (see --client).
+    /// this avoids loading fonts and connecting to the compositor each time.
+    #[argh(switch)]
+    daemon: bool,
+
+    /// send the items to a running daemon (see --daemon) to be shown, instead
+    /// of opening a window ourselves.
+    #[argh(switch)]
+    client: bool,
+
+    /// print the names of the available themes and exit.
+    #[argh(switch)]
+    list_themes: bool,
+}
+
+// the item that was asked to be preselected, if any.
+fn preselect(args: &Args) -> Result<Option<grid::Preselect>, String> {
+    let preselects: Vec<_> = [
+        args.select.clone().map(grid::Preselect::Value),
+        args.select_index.map(grid::Preselect::Index),
+        args.select_display.clone().map(grid::Preselect::Display),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if preselects.len() > 1 {
+        return Err(
+            "only one of --select, --select-index and --select-display can be used.".to_string(),
+        );
+    }
+
+    Ok(preselects.into_iter().next())
 }
 
-fn read_options(delimiter: Option<&str>) -> Vec<(String, Option<String>)> {
-    let stdin = io::stdin();
-    let lines = stdin.lock().lines();
+// how the lines on stdin, and printed by submenu commands, are read.
+fn format(args: &Args) -> input::Format {
+    input::Format {
+        delimiter: args.delimiter.clone(),
+        icons: args.icons,
+        groups: args.groups,
+        json: args.json,
+    }
+}
+
+// add a line from stdin to the choices. returns true if the shown choices changed.
+fn push_line(
+    choices: &mut input::Choices,
+    format: &input::Format,
+    tree: bool,
+    line: String,
+) -> bool {
+    let (indent, line) = match tree {
+        true => input::indentation(&line),
+        false => (0, line),
+    };
+
+    match format.parse(line) {
+        Ok(choice) => choices.push_indented(indent, choice),
+        Err(e) => {
+            eprintln!("{e:#}");
+            false
+        }
+    }
+}
+
+// show the picker in a new window, returning the chosen item.
+//
+// in stdin mode, the window is shown straight away and items are added as they're read.
+fn show_window(
+    args: &Args,
+    config: config::Config,
+    choices: input::Choices,
+) -> Option<state::Selection> {
+    let context = window::Context::new(&config).unwrap();
+    let grid = grid::Grid::new(choices.shown()).unwrap();
+    let (mut window, mut event_loop) =
+        window::Window::new(context, config, grid, choices.preselected());
+    window.set_format(format(args));
+
+    // the items that are read after the window has opened, and what to say if there aren't any.
+    let lines = match (&args.script, &args.mode) {
+        (Some(script), _) => Some((
+            script::run(script, None).unwrap_or_else(|e| {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            }),
+            "the script didn't print any options, exiting.",
+        )),
+        (None, Mode::Stdin) => Some((
+            input::read_lines(io::BufReader::new(io::stdin())),
+            "no options were provided on stdin, exiting.",
+        )),
+        (None, _) => None,
+    };
+    let mut lines = lines.map(|(lines, on_empty)| {
+        window.set_loading(true);
+        insert_lines(&event_loop.handle(), lines, args, choices, Some(on_empty))
+    });
+
+    // We don't draw immediately, the configure will notify us when to first draw.
+    loop {
+        event_loop.dispatch(None, &mut window).unwrap();
+
+        if !window.should_exit() {
+            continue;
+        }
 
-    lines
-        .map(|r| {
-            let line = r.expect("failed to read line");
-            match delimiter.and_then(|d| line.split_once(d)) {
-                Some((value, display)) => (value.to_string(), Some(display.to_string())),
-                None => (line, None),
+        // the script is told what was chosen, and decides whether we carry on.
+        let (Some(script), Some(value)) = (&args.script, window.selected_value()) else {
+            break;
+        };
+        let response = script::run(script, Some(value)).unwrap_or_else(|e| {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        });
+
+        // anything that the script was still printing is out of date
+        if let Some(token) = lines.take() {
+            event_loop.handle().remove(token);
+        }
+        window.resume();
+        window.set_loading(true);
+        lines = Some(insert_lines(
+            &event_loop.handle(),
+            response,
+            args,
+            input::Choices::new(None, 0, None),
+            None,
+        ));
+    }
+
+    let selected = window.selected().cloned();
+
+    // make sure that the window has gone before anything is done with the choice.
+    window.into_context(event_loop);
+
+    selected
+}
+
+// show the choices that arrive on `lines` in the window, in place of the ones
+// that it's showing.
+//
+// if there aren't any, we exit, saying why if there's an `on_empty` message.
+fn insert_lines(
+    handle: &LoopHandle<'static, window::Window>,
+    lines: input::Lines,
+    args: &Args,
+    mut choices: input::Choices,
+    on_empty: Option<&'static str>,
+) -> RegistrationToken {
+    let format = format(args);
+    let tree = args.tree;
+
+    handle
+        .insert_source(lines, move |event, _, window| match event {
+            channel::Event::Msg(Some(line)) => {
+                let was_preselected = choices.preselected().is_some();
+
+                if push_line(&mut choices, &format, tree, line)
+                    && let Err(e) = window.set_choices(choices.shown())
+                {
+                    eprintln!("{e:#}");
+                    window.exit();
+                }
+
+                if let Some(value) = choices.preselected().filter(|_| !was_preselected) {
+                    window.set_initial_value(value.to_string());
+                }
             }
+            channel::Event::Msg(None) => {
+                if choices.received() == 0 {
+                    if let Some(message) = on_empty {
+                        println!("{message}");
+                    }
+                    window.exit();
+                    return;
+                }
+
+                choices.warn_missing();
+                window.set_loading(false);
+            }
+            channel::Event::Closed => {}
         })
-        .collect()
+        .unwrap()
 }
 
 fn main() {
     env_logger::init();
 
     let args: Args = argh::from_env();
-    let config = config::Config::load(args.config_file).unwrap();
 
-    let options = read_options(args.delimiter.as_deref()).to_vec();
+    if args.list_themes {
+        for name in theme::available() {
+            println!("{name}");
+        }
+        return;
+    }
 
-    if options.is_empty() {
-        println!("no options were provided on stdin, exiting.");
+    if args.serve_clipboard {
+        if let Err(e) = clipboard::serve_from_stdin() {
+            log::error!("{e:#}");
+        }
         return;
     }
 
-    let (mut window, mut event_loop) = window::Window::new(config, &options).unwrap();
+    if args.icons && args.delimiter.is_none() {
+        eprintln!("--icons requires a delimiter (-d).");
+        std::process::exit(1);
+    }
 
-    // We don't draw immediately, the configure will notify us when to first draw.
-    loop {
-        event_loop.dispatch(None, &mut window).unwrap();
+    if args.groups && args.delimiter.is_none() {
+        eprintln!("--groups requires a delimiter (-d).");
+        std::process::exit(1);
+    }
 
-        if window.should_exit() {
-            break;
+    if args.script.is_some() {
+        let conflict = match args.mode {
+            Mode::Stdin if args.client => Some("--client"),
+            Mode::Stdin if args.history.is_some() => Some("--history"),
+            Mode::Stdin => None,
+            Mode::Drun | Mode::Windows => Some("--mode"),
+        };
+        if let Some(option) = conflict {
+            eprintln!("--script can't be used with {option}.");
+            std::process::exit(1);
+        }
+    }
+
+    if args.tree && args.json {
+        eprintln!("--tree can't be used with --json, which has children instead.");
+        std::process::exit(1);
+    }
+
+    if args.daemon {
+        let result =
+            daemon::serve(|| config::Config::load(args.config_file.clone(), args.theme.as_deref()));
+        if let Err(e) = result {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.clipboard && (!matches!(args.mode, Mode::Stdin) || args.script.is_some()) {
+        eprintln!("--clipboard can only be used with items from stdin.");
+        std::process::exit(1);
+    }
+    if args.clipboard && args.exec.is_some() {
+        eprintln!("--clipboard can't be used with --exec.");
+        std::process::exit(1);
+    }
+
+    let exec = args.exec.as_deref().map(|template| {
+        if !matches!(args.mode, Mode::Stdin) || args.script.is_some() {
+            eprintln!("--exec can only be used with items from stdin.");
+            std::process::exit(1);
+        }
+        exec::Template::parse(template).unwrap_or_else(|e| {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        })
+    });
+
+    let preselect = preselect(&args).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+
+    if args.history.is_some() && matches!(args.mode, Mode::Windows) {
+        eprintln!("--history can't be used with --mode windows.");
+        std::process::exit(1);
+    }
+
+    let now = history::now();
+    let mut history = args
+        .history
+        .as_deref()
+        .map(history::History::load)
+        .transpose()
+        .unwrap_or_else(|e| {
+            eprintln!("{e:#}, continuing without history.");
+            None
+        });
+
+    // choices are ranked as they arrive, so that frequently chosen items always make the cut.
+    let mut choices = input::Choices::new(history.clone(), now, preselect);
+
+    let entries = match args.mode {
+        Mode::Drun => drun::entries(&drun::data_dirs(), &drun::Environment::from_env()),
+        _ => vec![],
+    };
+
+    let toplevels = match args.mode {
+        Mode::Windows => {
+            let conn = wayland_client::Connection::connect_to_env().unwrap();
+            Some(toplevel::Toplevels::list(&conn).unwrap())
+        }
+        _ => None,
+    };
+
+    // stdin is read after the window has opened, the others are ready now.
+    let options = match args.mode {
+        Mode::Stdin => vec![],
+        Mode::Drun => entries.iter().map(drun::DesktopEntry::choice).collect(),
+        Mode::Windows => toplevels.as_ref().unwrap().choices(&drun::data_dirs()),
+    };
+
+    if options.is_empty() {
+        match args.mode {
+            Mode::Stdin => {}
+            Mode::Drun => {
+                println!("no applications were found, exiting.");
+                return;
+            }
+            Mode::Windows => {
+                println!("no windows are open, exiting.");
+                return;
+            }
+        }
+    } else {
+        for option in options {
+            choices.push(option);
+        }
+        choices.warn_missing();
+    }
+
+    let selected = if args.client {
+        if let Mode::Stdin = args.mode {
+            // the daemon is sent all of the items at once.
+            let format = format(&args);
+            for line in io::stdin().lines() {
+                let line = line.expect("failed to read line");
+                push_line(&mut choices, &format, args.tree, line);
+            }
+
+            if choices.received() == 0 {
+                println!("no options were provided on stdin, exiting.");
+                return;
+            }
+            choices.warn_missing();
+        }
+
+        let request = daemon::Request {
+            choices: choices.shown().to_vec(),
+            initial_value: choices.preselected().map(str::to_string),
+            format: format(&args),
+        };
+        daemon::request(&request).unwrap_or_else(|e| {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        })
+    } else {
+        let config = config::Config::load(args.config_file.clone(), args.theme.as_deref()).unwrap();
+        show_window(&args, config, choices)
+    };
+
+    // the script has already acted on whatever was chosen
+    let Some(selection) = selected.filter(|_| args.script.is_none()) else {
+        return;
+    };
+    let value = selection.value.as_str();
+
+    if let Some(history) = &mut history {
+        history.record(value, now);
+        if let Err(e) = history.save(now) {
+            eprintln!("{e:#}");
+        }
+    }
+
+    match args.mode {
+        Mode::Stdin => {
+            let result = match &exec {
+                Some(template) => template.spawn(&selection),
+                None if args.clipboard => clipboard::copy_in_background(value),
+                None => {
+                    println!("{value}");
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            }
+        }
+        Mode::Drun => {
+            let entry = entries
+                .iter()
+                .find(|e| e.id == value)
+                .expect("selected an unknown application");
+
+            if args.dry_run {
+                println!("{}", drun::shell_join(&entry.command()));
+            } else if let Err(e) = entry.launch() {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            }
+        }
+        Mode::Windows => {
+            if let Err(e) = toplevels.unwrap().activate(value) {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            }
         }
     }
 }
@@ -1,6 +1,8 @@
-use crate::{colour, config, grid, layout, text};
+use crate::{colour, config, grid, icon, layout, text};
 use cosmic_text;
 use raqote::{DrawOptions, PathBuilder, Source};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 pub type DrawTarget<'a> = raqote::DrawTarget<&'a mut [u32]>;
 
@@ -9,65 +11,210 @@ pub struct DrawArgs<'a> {
     pub config: &'a config::Config,
     pub font_system: &'a mut cosmic_text::FontSystem,
     pub swash_cache: &'a mut cosmic_text::SwashCache,
+    pub icon_cache: &'a mut icon::Cache,
     pub scale: f32,
 }
 
+// the logical size of a layer that fits the grid, below the query strip.
+pub fn layer_size(config: &config::Config, grid: &grid::Grid) -> (u32, u32) {
+    // an empty grid still gets room for one item, so that the layer has a size.
+    let border_width = config.max_border_width();
+    let width = grid.width.max(1) as u32
+        * (config.item_width + config.item_margin + 2. * border_width) as u32;
+    let height = grid.height.max(1) as u32
+        * (config.item_height + config.item_margin + 2. * border_width) as u32;
+    (width, height + grid_top(config).ceil() as u32)
+}
+
+// how far down the layer the grid starts. the space above it is kept for the
+// query strip, so that the strip doesn't cover the top row.
+fn grid_top(config: &config::Config) -> f32 {
+    let (pos, space) = query_strip_rect(
+        config,
+        &layout::Space {
+            width: 0.,
+            height: 0.,
+        },
+    );
+    pos.y + space.height
+}
+
+// the area that the cell at a grid position occupies, in pixels.
+fn cell_rect(
+    config: &config::Config,
+    layer_space: &layout::Space,
+    scale: f32,
+    position: &grid::GridPosition,
+) -> (layout::ScreenPosition, layout::Space) {
+    let top = grid_top(config);
+    let grid_space = layout::Space {
+        width: layer_space.width,
+        height: layer_space.height - top,
+    };
+
+    let (pos, space) = layout::grid_position_to_screen(
+        &grid_space.scale(scale),
+        position,
+        config.item_width * scale,
+        config.item_height * scale,
+        config.item_margin * scale,
+    );
+    (
+        layout::ScreenPosition {
+            x: pos.x,
+            y: pos.y + top * scale,
+        },
+        space,
+    )
+}
+
 pub struct DrawableItem {
     text: text::Text,
     pub grid_position: grid::GridPosition,
     normal_bg_colour: colour::Colour,
+    normal_fg_colour: colour::Colour,
+    icon: Option<PathBuf>,
+
+    // what the item was made from, to tell whether it can be reused
+    value: String,
+    icon_spec: Option<String>,
 }
 
-impl DrawableItem {
-    pub fn new(
-        grid_position: grid::GridPosition,
-        text: String,
-        font_name: Option<String>,
-        size: f32,
-        normal_bg_colour: colour::Colour,
-    ) -> DrawableItem {
-        let t = text::Text::new(text, font_name, size);
-        DrawableItem {
-            text: t,
-            grid_position,
-            normal_bg_colour,
-        }
-    }
+// the name of a section, drawn across the top of its columns.
+struct Title {
+    text: text::Text,
+    left: grid::GridPosition,
+    right: grid::GridPosition,
 }
 
 pub struct DrawableItems {
-    items: Vec<DrawableItem>,
+    items: grid::Items<DrawableItem>,
+    titles: Vec<Title>,
 }
 
 impl DrawableItems {
     pub fn from_grid(config: &config::Config, grid: &grid::Grid) -> DrawableItems {
-        let mut cycle_colours = config.bg_colour.cycle();
-
-        let dis = grid
-            .items_iter()
-            .map(|i| {
-                let normal_bg_colour = cycle_colours.next().unwrap();
-                DrawableItem::new(
-                    i.position.clone(),
-                    i.display.clone(),
-                    config.font_name.clone(),
-                    config.font_size,
-                    normal_bg_colour.clone(),
-                )
-            })
+        Self::reusing(config, grid, HashMap::new())
+    }
+
+    // replace the items with the grid's, e.g. as more are read.
+    //
+    // items whose text and icon haven't changed keep their rendered text and
+    // the icon that was found for them, so that adding an item doesn't mean
+    // doing that work again for every other item.
+    pub fn update(&mut self, config: &config::Config, grid: &grid::Grid) {
+        let previous = self
+            .items
+            .take()
+            .into_iter()
+            .map(|di| (di.value.clone(), di))
             .collect();
+        *self = Self::reusing(config, grid, previous);
+    }
+
+    // `previous` is the items that can be reused, by value.
+    fn reusing(
+        config: &config::Config,
+        grid: &grid::Grid,
+        mut previous: HashMap<String, DrawableItem>,
+    ) -> DrawableItems {
+        let text_style: text::Style = config.param();
+
+        let items = grid.items.map(|idx, i| {
+            let normal_bg_colour = config.bg_colour.colour_for(idx, &i.value);
+            let normal_fg_colour = config.fg_colour.resolve(&normal_bg_colour);
+            // submenus are marked, so that it's clear that choosing them won't exit
+            let display = match i.submenu {
+                Some(_) => format!("{} ›", i.display),
+                None => i.display.clone(),
+            };
+
+            let reused = previous
+                .remove(&i.value)
+                .filter(|di| di.text.as_str() == display && di.icon_spec == i.icon);
+            let (text, icon) = match reused {
+                Some(di) => (di.text, di.icon),
+                None => (
+                    text::Text::new(display, text_style.clone()),
+                    i.icon.as_deref().and_then(|spec| {
+                        let path = icon::resolve(
+                            spec,
+                            config.icon_theme.as_deref(),
+                            config.icon_size as u16,
+                        );
+                        if path.is_none() {
+                            log::warn!("could not find icon {spec:?}");
+                        }
+                        path
+                    }),
+                ),
+            };
+
+            DrawableItem {
+                text,
+                grid_position: i.position.clone(),
+                normal_bg_colour,
+                normal_fg_colour,
+                icon,
+                value: i.value.clone(),
+                icon_spec: i.icon.clone(),
+            }
+        });
+
+        let titles = grid.title_row().map_or(vec![], |row| {
+            grid.items
+                .sections()
+                .iter()
+                .filter_map(|section| {
+                    Some(Title {
+                        text: text::Text::new(section.title.clone()?, text_style.clone()),
+                        left: grid::GridPosition::new(section.left, row),
+                        right: grid::GridPosition::new(section.right, row),
+                    })
+                })
+                .collect()
+        });
+
+        DrawableItems { items, titles }
+    }
 
-        DrawableItems { items: dis }
+    // forget each item's rendered text, e.g. because the scale has changed.
+    pub fn clear_caches(&self) {
+        for di in self.items.iter() {
+            di.text.clear_cache();
+        }
+        for title in &self.titles {
+            title.text.clear_cache();
+        }
     }
 
-    pub fn at_positions(&self, positions: &[grid::GridPosition]) -> Vec<&DrawableItem> {
-        // TODO: scanning every DI here is not efficient.
-        // this is called every time the cursor moves.
-        self.items
-            .iter()
-            .filter(|di| positions.contains(&di.grid_position))
-            .collect()
+    pub fn at(&self, position: &grid::GridPosition) -> Option<&DrawableItem> {
+        self.items.at(position)
+    }
+}
+
+// a rectangle with corners rounded to the given radius.
+fn rounded_rect(x: f32, y: f32, width: f32, height: f32, radius: f32) -> raqote::Path {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    let mut pb = PathBuilder::new();
+
+    let r = radius.min(width / 2.).min(height / 2.);
+    if r <= 0. {
+        pb.rect(x, y, width, height);
+        return pb.finish();
     }
+
+    // clockwise from the top left corner.
+    // each arc begins with a line from the end of the previous one.
+    pb.move_to(x, y + r);
+    pb.arc(x + r, y + r, r, PI, FRAC_PI_2);
+    pb.arc(x + width - r, y + r, r, 3. * FRAC_PI_2, FRAC_PI_2);
+    pb.arc(x + width - r, y + height - r, r, 0., FRAC_PI_2);
+    pb.arc(x + r, y + height - r, r, FRAC_PI_2, FRAC_PI_2);
+    pb.close();
+
+    pb.finish()
 }
 
 fn grid_item_rect(
@@ -76,11 +223,16 @@ fn grid_item_rect(
     item_space: &layout::Space,
     is_selected: bool,
     normal_bg_colour: &colour::Colour,
+    scale: f32,
     dt: &mut DrawTarget,
 ) {
-    let mut pb = PathBuilder::new();
-    pb.rect(item_pos.x, item_pos.y, item_space.width, item_space.height);
-    let path = pb.finish();
+    let path = rounded_rect(
+        item_pos.x,
+        item_pos.y,
+        item_space.width,
+        item_space.height,
+        config.corner_radius * scale,
+    );
 
     let bg_colour = if is_selected {
         &config.active_bg_colour
@@ -93,61 +245,218 @@ fn grid_item_rect(
         &DrawOptions::default(),
     );
 
-    dt.stroke(
-        &path,
-        &Source::Solid(config.border_colour.as_source()),
-        &raqote::StrokeStyle {
-            width: config.border_width,
+    let (border_colour, border_width) = config.border_style(is_selected);
+    if border_width > 0. {
+        dt.stroke(
+            &path,
+            &Source::Solid(border_colour.as_source()),
+            &raqote::StrokeStyle {
+                width: border_width * scale,
+                ..Default::default()
+            },
+            &DrawOptions::default(),
+        );
+    }
+}
+
+// the area that an item occupies, in pixels.
+fn item_rect(di: &DrawableItem, draw_args: &DrawArgs) -> (layout::ScreenPosition, layout::Space) {
+    cell_rect(
+        draw_args.config,
+        draw_args.layer_space,
+        draw_args.scale,
+        &di.grid_position,
+    )
+}
+
+// how far borders extend outside of an item, in pixels.
+//
+// borders are stroked along the edge of the item, so half of their width lies
+// outside it. leave an extra pixel for antialiasing.
+fn border_overflow(draw_args: &DrawArgs) -> f32 {
+    draw_args.config.max_border_width() * draw_args.scale / 2. + 1.
+}
+
+// erase a rectangle, e.g. to remove whatever was drawn there before.
+fn clear_rect(dt: &mut DrawTarget, pos: &layout::ScreenPosition, space: &layout::Space) {
+    dt.fill_rect(
+        pos.x,
+        pos.y,
+        space.width,
+        space.height,
+        &Source::Solid(raqote::SolidSource {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }),
+        &DrawOptions {
+            blend_mode: raqote::BlendMode::Src,
             ..Default::default()
         },
-        &DrawOptions::default(),
     );
 }
 
+// keep drawing inside a rectangle until the clip is popped. the rectangle is
+// transformed like anything else that's drawn (e.g. while the window opens).
+fn push_clip(dt: &mut DrawTarget, pos: &layout::ScreenPosition, space: &layout::Space) {
+    let transform = dt.get_transform();
+    let a = transform.transform_point(raqote::Point::new(pos.x, pos.y));
+    let b = transform.transform_point(raqote::Point::new(
+        pos.x + space.width,
+        pos.y + space.height,
+    ));
+    dt.push_clip_rect(raqote::IntRect::new(
+        raqote::IntPoint::new(a.x.min(b.x).floor() as i32, a.y.min(b.y).floor() as i32),
+        raqote::IntPoint::new(a.x.max(b.x).ceil() as i32, a.y.max(b.y).ceil() as i32),
+    ));
+}
+
+// draw a single item.
+//
+// returns the area that was drawn to, including any border that extends outside the item.
 pub fn draw_grid_item(
     dt: &mut DrawTarget,
     di: &DrawableItem,
     cursor_position: &grid::GridPosition,
     draw_args: &mut DrawArgs,
 ) -> (layout::ScreenPosition, layout::Space) {
-    let (item_pos, item_space) = layout::grid_position_to_screen(
-        &draw_args.layer_space.scale(draw_args.scale),
-        &di.grid_position,
-        draw_args.config.item_width * draw_args.scale,
-        draw_args.config.item_height * draw_args.scale,
-        draw_args.config.item_margin * draw_args.scale,
-    );
+    let config = draw_args.config;
+    let scale = draw_args.scale;
 
+    let (item_pos, item_space) = item_rect(di, draw_args);
     let is_selected = cursor_position == &di.grid_position;
 
+    // clear whatever was drawn here before (e.g. a wider active border).
+    // we stay within our half of the margin so that we don't erase our
+    // neighbours, and a border that's wider than that is cut off there so
+    // that it doesn't draw over them either.
+    let overflow = border_overflow(draw_args);
+    let (drawn_pos, drawn_space) = layout::inflate(
+        &item_pos,
+        &item_space,
+        overflow.min(config.item_margin * scale / 2.),
+    );
+    clear_rect(dt, &drawn_pos, &drawn_space);
+    push_clip(dt, &drawn_pos, &drawn_space);
+
     // render the rectangle
     grid_item_rect(
-        draw_args.config,
+        config,
         &item_pos,
         &item_space,
         is_selected,
         &di.normal_bg_colour,
+        scale,
         dt,
     );
 
+    draw_item_content(dt, di, is_selected, draw_args);
+    dt.pop_clip();
+
+    (drawn_pos, drawn_space)
+}
+
+// draw an item's icon and text, on top of its background.
+fn draw_item_content(
+    dt: &mut DrawTarget,
+    di: &DrawableItem,
+    is_selected: bool,
+    draw_args: &mut DrawArgs,
+) {
+    let config = draw_args.config;
+    let scale = draw_args.scale;
+
+    let (item_pos, item_space) = item_rect(di, draw_args);
+
     // render the text
     let fg_colour = if is_selected {
-        &draw_args.config.active_fg_colour
+        config.active_fg_colour.resolve(&config.active_bg_colour)
     } else {
-        &draw_args.config.fg_colour
+        di.normal_fg_colour.clone()
     };
 
-    di.text.render_centred(
-        dt,
-        draw_args.font_system,
-        draw_args.swash_cache,
-        draw_args.scale,
-        fg_colour,
-        &item_space,
-        &item_pos,
-    );
+    let (content_pos, content_space) =
+        layout::inflate(&item_pos, &item_space, -config.item_padding * scale);
+
+    // make room for the icon, if there is one
+    let icon = di.icon.as_deref().and_then(|path| {
+        let size = (config.icon_size * scale)
+            .min(content_space.width)
+            .min(content_space.height)
+            .floor();
+        draw_args
+            .icon_cache
+            .get(path, size as u32)
+            .map(|icon| (icon, size))
+    });
+
+    let (text_pos, text_space) = match icon {
+        None => (content_pos, content_space),
+        Some((icon, size)) => {
+            let gap = config.item_padding * scale;
+            let (icon_pos, text_pos, text_space) = match config.icon_position {
+                icon::IconPosition::Left => (
+                    layout::ScreenPosition {
+                        x: content_pos.x,
+                        y: content_pos.y + (content_space.height - size) / 2.,
+                    },
+                    layout::ScreenPosition {
+                        x: content_pos.x + size + gap,
+                        y: content_pos.y,
+                    },
+                    layout::Space {
+                        width: (content_space.width - size - gap).max(0.),
+                        height: content_space.height,
+                    },
+                ),
+                icon::IconPosition::Top => (
+                    layout::ScreenPosition {
+                        x: content_pos.x + (content_space.width - size) / 2.,
+                        y: content_pos.y,
+                    },
+                    layout::ScreenPosition {
+                        x: content_pos.x,
+                        y: content_pos.y + size + gap,
+                    },
+                    layout::Space {
+                        width: content_space.width,
+                        height: (content_space.height - size - gap).max(0.),
+                    },
+                ),
+            };
 
-    (item_pos, item_space)
+            // keep the icon's aspect ratio within its square
+            let image = icon.as_image();
+            let icon_scale = size / image.width.max(image.height) as f32;
+            let (width, height) = (
+                image.width as f32 * icon_scale,
+                image.height as f32 * icon_scale,
+            );
+            dt.draw_image_with_size_at(
+                width,
+                height,
+                icon_pos.x + (size - width) / 2.,
+                icon_pos.y + (size - height) / 2.,
+                &image,
+                &DrawOptions::default(),
+            );
+
+            (text_pos, text_space)
+        }
+    };
+
+    if text_space.width >= 1. && text_space.height >= 1. {
+        di.text.render_centred(
+            dt,
+            draw_args.font_system,
+            draw_args.swash_cache,
+            scale,
+            &fg_colour,
+            &text_space,
+            &text_pos,
+        );
+    }
 }
 
 pub fn grid(
@@ -156,7 +465,535 @@ pub fn grid(
     cursor_position: &grid::GridPosition,
     draw_args: &mut DrawArgs,
 ) {
-    for di in &drawable_items.items {
+    for di in drawable_items.items.iter() {
         draw_grid_item(dt, di, cursor_position, draw_args);
     }
+    for title in &drawable_items.titles {
+        draw_title(dt, title, draw_args);
+    }
+}
+
+// draw a section's title, in a bar across the cells above its columns.
+fn draw_title(dt: &mut DrawTarget, title: &Title, draw_args: &mut DrawArgs) {
+    let config = draw_args.config;
+    let scale = draw_args.scale;
+
+    let cell = |position| cell_rect(config, draw_args.layer_space, scale, position);
+    let (pos, space) = cell(&title.left);
+    let (right_pos, right_space) = cell(&title.right);
+    let space = layout::Space {
+        width: right_pos.x + right_space.width - pos.x,
+        height: space.height,
+    };
+
+    let bg_colour = &config.border_colour;
+    dt.fill(
+        &rounded_rect(
+            pos.x,
+            pos.y,
+            space.width,
+            space.height,
+            config.corner_radius * scale,
+        ),
+        &Source::Solid(bg_colour.as_source()),
+        &DrawOptions::default(),
+    );
+
+    let (text_pos, text_space) = layout::inflate(&pos, &space, -config.item_padding * scale);
+    if text_space.width >= 1. && text_space.height >= 1. {
+        title.text.render_centred(
+            dt,
+            draw_args.font_system,
+            draw_args.swash_cache,
+            scale,
+            &bg_colour.readable_foreground(),
+            &text_space,
+            &text_pos,
+        );
+    }
+}
+
+// the transform for drawing the grid as it opens, `t` of the way through:
+// scaled up from the centre of the layer.
+pub fn opening_transform(t: f32, draw_args: &DrawArgs) -> raqote::Transform {
+    // the size that the grid starts at
+    const INITIAL_SCALE: f32 = 0.9;
+
+    let space = draw_args.layer_space.scale(draw_args.scale);
+    let (x, y) = (space.width / 2., space.height / 2.);
+    let s = INITIAL_SCALE + (1. - INITIAL_SCALE) * t;
+
+    raqote::Transform::translation(-x, -y)
+        .then_scale(s, s)
+        .then_translate(raqote::Vector::new(x, y))
+}
+
+// make everything that has been drawn partially transparent.
+pub fn fade(dt: &mut DrawTarget, alpha: f32) {
+    let alpha = (alpha.clamp(0., 1.) * 255.).round() as u32;
+    // the pixels are premultiplied, so every channel is scaled.
+    for pixel in dt.get_data_mut() {
+        let p = *pixel;
+        *pixel = [0, 8, 16, 24].iter().fold(0, |faded, shift| {
+            faded | (((p >> shift) & 0xff) * alpha / 255) << shift
+        });
+    }
+}
+
+// the cursor partway through sliding from one item to another, `t` of the way.
+//
+// the items in the area that the highlight crosses are redrawn, with the
+// highlight drawn between their backgrounds and their text.
+//
+// returns the area that was drawn to.
+pub fn cursor_slide(
+    dt: &mut DrawTarget,
+    drawable_items: &DrawableItems,
+    from: &grid::GridPosition,
+    to: &grid::GridPosition,
+    t: f32,
+    draw_args: &mut DrawArgs,
+) -> (layout::ScreenPosition, layout::Space) {
+    let config = draw_args.config;
+    let scale = draw_args.scale;
+
+    let rect = |position| cell_rect(config, draw_args.layer_space, scale, position);
+    let (from_pos, item_space) = rect(from);
+    let (to_pos, _) = rect(to);
+
+    let area_pos = layout::ScreenPosition {
+        x: from_pos.x.min(to_pos.x),
+        y: from_pos.y.min(to_pos.y),
+    };
+    let area_space = layout::Space {
+        width: (from_pos.x - to_pos.x).abs() + item_space.width,
+        height: (from_pos.y - to_pos.y).abs() + item_space.height,
+    };
+
+    // like draw_grid_item, we stay within our half of the margin
+    let overflow = border_overflow(draw_args);
+    let (drawn_pos, drawn_space) = layout::inflate(
+        &area_pos,
+        &area_space,
+        overflow.min(config.item_margin * scale / 2.),
+    );
+    clear_rect(dt, &drawn_pos, &drawn_space);
+    push_clip(dt, &drawn_pos, &drawn_space);
+
+    let items: Vec<_> = (from.x.min(to.x)..=from.x.max(to.x))
+        .flat_map(|x| {
+            (from.y.min(to.y)..=from.y.max(to.y)).map(move |y| grid::GridPosition::new(x, y))
+        })
+        .filter_map(|position| drawable_items.at(&position))
+        .collect();
+
+    for di in &items {
+        let (item_pos, item_space) = item_rect(di, draw_args);
+        grid_item_rect(
+            config,
+            &item_pos,
+            &item_space,
+            false,
+            &di.normal_bg_colour,
+            scale,
+            dt,
+        );
+    }
+
+    let highlight_pos = layout::ScreenPosition {
+        x: from_pos.x + (to_pos.x - from_pos.x) * t,
+        y: from_pos.y + (to_pos.y - from_pos.y) * t,
+    };
+    grid_item_rect(
+        config,
+        &highlight_pos,
+        &item_space,
+        true,
+        &config.active_bg_colour,
+        scale,
+        dt,
+    );
+
+    // text is coloured as if the cursor is on whichever item the highlight mostly covers
+    let selected = if t < 0.5 { from } else { to };
+    for di in items {
+        draw_item_content(dt, di, &di.grid_position == selected, draw_args);
+    }
+    dt.pop_clip();
+
+    (drawn_pos, drawn_space)
+}
+
+// the chosen item, grown into the margin around it by `amount` (from 0 to 1).
+//
+// returns the area that was drawn to.
+pub fn selection_pulse(
+    dt: &mut DrawTarget,
+    di: &DrawableItem,
+    amount: f32,
+    draw_args: &mut DrawArgs,
+) -> (layout::ScreenPosition, layout::Space) {
+    let config = draw_args.config;
+    let scale = draw_args.scale;
+
+    let (item_pos, item_space) = item_rect(di, draw_args);
+    let overflow = border_overflow(draw_args);
+    let half_margin = config.item_margin * scale / 2.;
+
+    // stay within our half of the margin, including the border
+    let grow = (half_margin - overflow).max(0.) * amount;
+
+    let (clear_pos, clear_space) = layout::inflate(&item_pos, &item_space, half_margin);
+    clear_rect(dt, &clear_pos, &clear_space);
+
+    let (pulse_pos, pulse_space) = layout::inflate(&item_pos, &item_space, grow);
+    grid_item_rect(
+        config,
+        &pulse_pos,
+        &pulse_space,
+        true,
+        &config.active_bg_colour,
+        scale,
+        dt,
+    );
+    draw_item_content(dt, di, true, draw_args);
+
+    layout::inflate(&item_pos, &item_space, half_margin.max(overflow))
+}
+
+// a dot in the corner of the layer, shown while more items are being read.
+pub fn loading_indicator(dt: &mut DrawTarget, draw_args: &mut DrawArgs) {
+    let config = draw_args.config;
+    let scale = draw_args.scale;
+
+    let radius = (config.item_margin / 4.).max(2.) * scale;
+    let inset = radius + scale;
+
+    let mut pb = raqote::PathBuilder::new();
+    pb.arc(inset, inset, radius, 0., 2. * std::f32::consts::PI);
+    pb.close();
+
+    dt.fill(
+        &pb.finish(),
+        &Source::Solid(config.active_bg_colour.as_source()),
+        &DrawOptions::new(),
+    );
+}
+
+// where the query is shown, in a strip across the top of the layer (see
+// grid_top).
+pub fn query_strip_rect(
+    config: &config::Config,
+    layer_space: &layout::Space,
+) -> (layout::ScreenPosition, layout::Space) {
+    let inset = config.item_margin / 2.;
+    let height = (2. * config.font_size + 2. * config.item_padding).min(config.item_height);
+    (
+        layout::ScreenPosition { x: inset, y: inset },
+        layout::Space {
+            width: (layer_space.width - 2. * inset).max(0.),
+            height,
+        },
+    )
+}
+
+// the text that's been typed to find an item, followed by any text that's
+// still being composed with an input method. the strip is underlined while
+// there is.
+pub fn query_strip(
+    dt: &mut DrawTarget,
+    query: &str,
+    preedit: &str,
+    draw_args: &mut DrawArgs,
+) -> (layout::ScreenPosition, layout::Space) {
+    let config = draw_args.config;
+    let scale = draw_args.scale;
+
+    let (pos, space) = query_strip_rect(config, draw_args.layer_space);
+    let pos = layout::ScreenPosition {
+        x: pos.x * scale,
+        y: pos.y * scale,
+    };
+    let space = space.scale(scale);
+
+    let bg_colour = &config.border_colour;
+    let fg_colour = bg_colour.readable_foreground();
+    dt.fill(
+        &rounded_rect(
+            pos.x,
+            pos.y,
+            space.width,
+            space.height,
+            config.corner_radius * scale,
+        ),
+        &Source::Solid(bg_colour.as_source()),
+        &DrawOptions::default(),
+    );
+
+    let (text_pos, text_space) = layout::inflate(&pos, &space, -config.item_padding * scale);
+    if text_space.width < 1. || text_space.height < 1. {
+        return (pos, space);
+    }
+
+    let style = text::Style {
+        overflow: text::TextOverflow::Ellipsis,
+        ..config.param()
+    };
+    text::Text::new(format!("{query}{preedit}"), style).render_centred(
+        dt,
+        draw_args.font_system,
+        draw_args.swash_cache,
+        scale,
+        &fg_colour,
+        &text_space,
+        &text_pos,
+    );
+
+    if !preedit.is_empty() {
+        dt.fill_rect(
+            text_pos.x,
+            text_pos.y + text_space.height - scale,
+            text_space.width,
+            scale,
+            &Source::Solid(fg_colour.as_source()),
+            &DrawOptions::default(),
+        );
+    }
+
+    (pos, space)
+}
+
+// draws a grid into memory rather than a window.
+//
+// this does everything a window does to draw, apart from talking to the
+// compositor (see benches/).
+pub struct Offscreen<'a> {
+    config: &'a config::Config,
+    font_system: &'a mut cosmic_text::FontSystem,
+    swash_cache: cosmic_text::SwashCache,
+    icon_cache: icon::Cache,
+    drawable_items: DrawableItems,
+    space: layout::Space,
+    scale: u16,
+    pixels: Vec<u32>,
+}
+
+impl<'a> Offscreen<'a> {
+    pub fn new(
+        config: &'a config::Config,
+        choices: &[grid::Choice],
+        font_system: &'a mut cosmic_text::FontSystem,
+        scale: u16,
+    ) -> anyhow::Result<Offscreen<'a>> {
+        let grid = grid::Grid::new(choices)?;
+        let (width, height) = layer_size(config, &grid);
+
+        Ok(Offscreen {
+            config,
+            font_system,
+            swash_cache: cosmic_text::SwashCache::new(),
+            icon_cache: icon::Cache::default(),
+            drawable_items: DrawableItems::from_grid(config, &grid),
+            space: layout::Space {
+                width: width as f32,
+                height: height as f32,
+            },
+            scale,
+            pixels: vec![0; (width * height) as usize * (scale as usize).pow(2)],
+        })
+    }
+
+    // draw to our pixels, like a window draws to its buffer.
+    fn draw_with(&mut self, f: impl FnOnce(&mut DrawTarget, &DrawableItems, &mut DrawArgs)) {
+        let scale = self.scale as i32;
+        let mut dt = DrawTarget::from_backing(
+            self.space.width as i32 * scale,
+            self.space.height as i32 * scale,
+            &mut self.pixels[..],
+        );
+        let mut draw_args = DrawArgs {
+            layer_space: &self.space,
+            config: self.config,
+            font_system: self.font_system,
+            swash_cache: &mut self.swash_cache,
+            icon_cache: &mut self.icon_cache,
+            scale: self.scale as f32,
+        };
+
+        f(&mut dt, &self.drawable_items, &mut draw_args);
+    }
+
+    // draw the whole grid, returning the pixels.
+    pub fn draw(&mut self, cursor_position: &grid::GridPosition) -> &[u32] {
+        self.draw_with(|dt, drawable_items, draw_args| {
+            grid(dt, drawable_items, cursor_position, draw_args)
+        });
+        &self.pixels
+    }
+
+    // redraw the items that change when the cursor moves, like a window does.
+    pub fn move_cursor(&mut self, from: &grid::GridPosition, to: &grid::GridPosition) -> &[u32] {
+        self.draw_with(|dt, drawable_items, draw_args| {
+            for di in [from, to].into_iter().filter_map(|p| drawable_items.at(p)) {
+                draw_grid_item(dt, di, to, draw_args);
+            }
+        });
+        &self.pixels
+    }
+
+    // draw one frame of the cursor sliding between items, `t` of the way.
+    pub fn slide_cursor(
+        &mut self,
+        from: &grid::GridPosition,
+        to: &grid::GridPosition,
+        t: f32,
+    ) -> &[u32] {
+        self.draw_with(|dt, drawable_items, draw_args| {
+            cursor_slide(dt, drawable_items, from, to, t, draw_args);
+        });
+        &self.pixels
+    }
+
+    pub fn clear_caches(&mut self) {
+        self.drawable_items.clear_caches();
+    }
+
+    // replace the items, like a window does as more are read.
+    pub fn set_choices(&mut self, choices: &[grid::Choice]) -> anyhow::Result<()> {
+        let grid = grid::Grid::new(choices)?;
+        let (width, height) = layer_size(self.config, &grid);
+
+        self.drawable_items.update(self.config, &grid);
+        self.space = layout::Space {
+            width: width as f32,
+            height: height as f32,
+        };
+        self.pixels = vec![0; (width * height) as usize * (self.scale as usize).pow(2)];
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a font of our own, so that drawing doesn't depend on what's installed
+    fn font_system() -> cosmic_text::FontSystem {
+        let font =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fonts/Tuffy.ttf");
+        let mut db = crate::fonts::database_from_paths([&font]);
+        db.set_sans_serif_family("Tuffy");
+        cosmic_text::FontSystem::new_with_locale_and_db("en-US".into(), db)
+    }
+
+    #[test]
+    fn test_wide_border_is_redrawn() {
+        let mut font_system = font_system();
+
+        // the active border is much wider than the margin between items
+        let config = config::Config {
+            item_margin: 4.,
+            active_border_width: Some(16.),
+            ..config::Config::default()
+        };
+        let choices = [grid::Choice::new("a".into()), grid::Choice::new("b".into())];
+        let (centre, right) = (grid::GridPosition::new(0, 0), grid::GridPosition::new(1, 0));
+
+        let mut moved = Offscreen::new(&config, &choices, &mut font_system, 1).unwrap();
+        moved.draw(&centre);
+        let moved = moved.move_cursor(&centre, &right).to_vec();
+
+        // moving the cursor leaves nothing behind
+        let mut fresh = Offscreen::new(&config, &choices, &mut font_system, 1).unwrap();
+        assert!(moved == fresh.draw(&right));
+    }
+
+    #[test]
+    fn test_query_strip_clears_grid() {
+        let config = config::Config {
+            active_border_width: Some(6.),
+            ..config::Config::default()
+        };
+        let items = (0..20).map(|i| grid::Choice::new(i.to_string()));
+        let grouped = (0..6).map(|i| grid::Choice {
+            group: Some((i % 2).to_string()),
+            ..grid::Choice::new(i.to_string())
+        });
+
+        for choices in [items.collect::<Vec<_>>(), grouped.collect()] {
+            let grid = grid::Grid::new(&choices).unwrap();
+            let (width, height) = layer_size(&config, &grid);
+            let layer_space = layout::Space {
+                width: width as f32,
+                height: height as f32,
+            };
+
+            let (strip_pos, strip_space) = query_strip_rect(&config, &layer_space);
+            let strip_bottom = strip_pos.y + strip_space.height;
+
+            // the top row is either items or section titles, and their
+            // borders are clear of the strip too
+            let top_row = -((grid.height / 2) as i8);
+            let (pos, _) = cell_rect(
+                &config,
+                &layer_space,
+                2.,
+                &grid::GridPosition::new(0, top_row),
+            );
+            assert!(pos.y / 2. - config.max_border_width() / 2. >= strip_bottom);
+
+            // and the grid still fits at the bottom
+            let bottom_row = top_row + grid.height as i8 - 1;
+            let (pos, space) = cell_rect(
+                &config,
+                &layer_space,
+                1.,
+                &grid::GridPosition::new(0, bottom_row),
+            );
+            assert!(pos.y + space.height <= layer_space.height);
+        }
+    }
+
+    #[test]
+    fn test_streamed_items_are_reused() {
+        let mut font_system = font_system();
+        let config = config::Config::default();
+
+        let icon = std::env::temp_dir().join(format!(
+            "grid-select-test-render-{}.png",
+            std::process::id()
+        ));
+        std::fs::write(&icon, b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let mut choices = vec![
+            grid::Choice {
+                icon: Some(icon.to_str().unwrap().to_string()),
+                ..grid::Choice::new("a".into())
+            },
+            grid::Choice::new("b".into()),
+        ];
+        let mut offscreen = Offscreen::new(&config, &choices, &mut font_system, 1).unwrap();
+        offscreen.draw(&grid::GridPosition::new(0, 0));
+
+        // the icon isn't looked for again
+        std::fs::remove_file(&icon).unwrap();
+
+        choices[1].display = Some("B".into());
+        choices.push(grid::Choice::new("c".into()));
+        offscreen.set_choices(&choices).unwrap();
+
+        let item = |value: &str| {
+            offscreen
+                .drawable_items
+                .items
+                .iter()
+                .find(|di| di.value == value)
+                .unwrap()
+        };
+        assert!(item("a").text.is_cached());
+        assert_eq!(Some(&icon), item("a").icon.as_ref());
+        // its text has changed
+        assert!(!item("b").text.is_cached());
+        assert!(!item("c").text.is_cached());
+    }
 }
@@ -0,0 +1,119 @@
+// items and actions provided by a script, like rofi's script modes.
+//
+// the script is run with no input to get the first items: one per line on
+// stdout, in the same format as the lines on stdin. when an item is chosen,
+// the script is run again with the chosen value on stdin, followed by a
+// newline. if it prints more items, they replace the ones that are shown.
+// if it prints nothing, we exit.
+//
+// a script can tell the first run from the others because its stdin is empty.
+
+use crate::input;
+use anyhow::{Context, Result};
+use smithay_client_toolkit::reexports::calloop::channel;
+use std::io::{BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// run the script in the background, delivering the lines that it prints to
+// the event loop as they arrive. they end when the script closes its stdout.
+pub fn run(script: &Path, value: Option<&str>) -> Result<input::Lines> {
+    let mut child = Command::new(script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {}", script.display()))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let input = value.map(|v| format!("{v}\n")).unwrap_or_default();
+    let name = script.display().to_string();
+
+    let (sender, channel) = channel::channel();
+
+    std::thread::spawn(move || {
+        // the script might not read its input, so a failure here isn't fatal.
+        // stdin is closed afterwards, so that the script sees the end of it.
+        if let Err(e) = stdin.write_all(input.as_bytes()) {
+            log::debug!("{name} didn't read the chosen value: {e}");
+        }
+        drop(stdin);
+
+        input::forward_lines(BufReader::new(stdout), sender);
+
+        match child.wait() {
+            Ok(status) if !status.success() => eprintln!("{name} failed: {status}"),
+            Ok(_) => {}
+            Err(e) => eprintln!("failed to wait for {name}: {e}"),
+        }
+    });
+
+    Ok(channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smithay_client_toolkit::reexports::calloop::EventLoop;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn temp_script(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "grid-select-test-script-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    // everything that the script prints.
+    fn output(script: &Path, value: Option<&str>) -> Vec<String> {
+        let mut event_loop = EventLoop::<(Vec<String>, bool)>::try_new().unwrap();
+        event_loop
+            .handle()
+            .insert_source(
+                run(script, value).unwrap(),
+                |event, _, (lines, closed)| match event {
+                    channel::Event::Msg(Some(line)) => lines.push(line),
+                    channel::Event::Msg(None) => *closed = true,
+                    channel::Event::Closed => {}
+                },
+            )
+            .unwrap();
+
+        let mut output = (vec![], false);
+        while !output.1 {
+            event_loop
+                .dispatch(std::time::Duration::from_secs(1), &mut output)
+                .unwrap();
+        }
+        output.0
+    }
+
+    #[test]
+    fn test_protocol() {
+        let script = temp_script(
+            "protocol",
+            "#!/bin/sh\n\
+             if read -r value; then\n\
+               [ \"$value\" = more ] && echo \"after $value\"\n\
+             else\n\
+               echo more; echo done\n\
+             fi\n\
+             exit 0\n",
+        );
+
+        assert_eq!(vec!["more", "done"], output(&script, None));
+        assert_eq!(vec!["after more"], output(&script, Some("more")));
+        assert!(output(&script, Some("done")).is_empty());
+
+        std::fs::remove_file(script).unwrap();
+    }
+
+    #[test]
+    fn test_missing_script() {
+        assert!(run(Path::new("/nonexistent/grid-select-script"), None).is_err());
+    }
+}
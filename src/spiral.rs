@@ -14,7 +14,7 @@ fn ring_diameter(ring: usize) -> usize {
 //   x
 //  x x
 //   x
-fn positions_in_ring(ring: usize) -> usize {
+pub fn positions_in_ring(ring: usize) -> usize {
     if ring == 0 { 1 } else { ring * 4 }
 }
 
@@ -25,8 +25,7 @@ fn positions_in_ring(ring: usize) -> usize {
 //   x
 //  xxx
 //   x
-#[allow(dead_code)]
-fn total_positions_within(ring: usize) -> usize {
+pub fn total_positions_within(ring: usize) -> usize {
     4 * (ring + 1) * ring / 2 + 1
 }
 
@@ -53,6 +52,56 @@ pub fn sufficient_diameter(desired_position_count: usize) -> usize {
     ring_diameter(largest_ring.ceil() as usize)
 }
 
+// the ring that a position is in, i.e. its distance from the centre in moves.
+pub fn ring_of(position: &GridPosition) -> usize {
+    position.x.unsigned_abs() as usize + position.y.unsigned_abs() as usize
+}
+
+// the position's index in the spiral, i.e. how many positions come before it.
+//
+// the inverse of SpiralGenerator, without generating the positions in between.
+pub fn index_of(position: &GridPosition) -> usize {
+    let (x, y) = (position.x as isize, position.y as isize);
+    let ring = ring_of(position);
+    if ring == 0 {
+        return 0;
+    }
+
+    // each ring is four diagonal sides of `ring` positions, starting at the bottom
+    let r = ring as isize;
+    let offset = if x >= 0 && y > 0 {
+        x
+    } else if x > 0 {
+        r - y
+    } else if y < 0 {
+        2 * r - x
+    } else {
+        3 * r + y
+    };
+
+    total_positions_within(ring - 1) + offset as usize
+}
+
+// the position at an index in the spiral. the inverse of index_of.
+pub fn position_at(index: usize) -> GridPosition {
+    if index == 0 {
+        return GridPosition::new(0, 0);
+    }
+
+    let ring = (sufficient_diameter(index + 1) - 1) / 2;
+    let offset = index - total_positions_within(ring - 1);
+    let (side, along) = ((offset / ring) as i8, (offset % ring) as i8);
+    let ring = ring as i8;
+
+    // along each side, moving clockwise from the bottom
+    match side {
+        0 => GridPosition::new(along, ring - along),
+        1 => GridPosition::new(ring - along, -along),
+        2 => GridPosition::new(-along, -(ring - along)),
+        _ => GridPosition::new(-(ring - along), along),
+    }
+}
+
 pub struct SpiralGenerator {
     // the next position we're going to return
     next_result: GridPosition,
@@ -83,6 +132,12 @@ impl SpiralGenerator {
     }
 }
 
+impl Default for SpiralGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Iterator for SpiralGenerator {
     type Item = GridPosition;
 
@@ -157,6 +212,14 @@ mod tests {
         assert_eq!(sg.next(), Some(GridPosition::new(0, -2)));
     }
 
+    #[test]
+    fn test_index_of() {
+        for (i, position) in SpiralGenerator::new().take(100).enumerate() {
+            assert_eq!(i, index_of(&position), "{position:?}");
+            assert_eq!(position, position_at(i));
+        }
+    }
+
     #[test]
     fn test_ring_diameter() {
         assert_eq!(1, ring_diameter(0));
@@ -1,4 +1,23 @@
-use crate::grid;
+use crate::{grid, spiral};
+use serde::{Deserialize, Serialize};
+
+// the item that was chosen.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Selection {
+    pub value: String,
+    pub display: String,
+    // where the item was in the input (or in its submenu), counting from 0
+    pub index: usize,
+}
+
+// where the cursor goes when there's nothing else to put it on: the first item,
+// which is in the centre of the grid unless it's divided into sections.
+fn start_position(grid: &grid::Grid) -> grid::GridPosition {
+    grid.items
+        .position_of(0)
+        .unwrap_or(grid::GridPosition::new(0, 0))
+}
 
 pub struct State {
     pub grid: grid::Grid,
@@ -6,68 +25,488 @@ pub struct State {
     // the current cursor position
     pub cursor_position: grid::GridPosition,
 
+    // the item that the cursor should start on, if it's not in the grid yet
+    pending_initial_value: Option<String>,
+
     // should we shut down?
     pub should_exit: bool,
 
+    // the item that the user chose, if any
+    pub selected: Option<Selection>,
+
     // does the entire window need to be redrawn?
     pub needs_redraw: bool,
 
     // the last cursor position we rendered
     pub rendered_cursor_position: grid::GridPosition,
+
+    // does moving off the edge of the grid wrap around to the other side?
+    pub wrap: bool,
+
+    // the menus that we've opened submenus from, outermost first
+    parents: Vec<Parent>,
+
+    // the text that's been typed to find an item, e.g. with an input method
+    pub query: String,
+}
+
+// a menu that's hidden while one of its submenus is shown.
+struct Parent {
+    grid: grid::Grid,
+    // where the cursor was, so that it can be put back
+    cursor_position: grid::GridPosition,
 }
 
 impl State {
-    pub fn new(grid: grid::Grid) -> State {
-        State {
+    // `initial_value` is the value of the item that the cursor should start on.
+    // if there's no such item the cursor starts in the centre of the first section.
+    pub fn new(grid: grid::Grid, initial_value: Option<&str>) -> State {
+        let start = start_position(&grid);
+        let mut state = State {
             grid,
-            cursor_position: grid::GridPosition::new(0, 0),
+            cursor_position: start,
+            pending_initial_value: None,
             should_exit: false,
+            selected: None,
             needs_redraw: true,
             rendered_cursor_position: grid::GridPosition::new(0, 0),
+            wrap: false,
+            parents: vec![],
+            query: String::new(),
+        };
+        if let Some(value) = initial_value {
+            state.set_initial_value(value.to_string());
         }
+        state.rendered_cursor_position = state.cursor_position.clone();
+        state
     }
 
-    pub fn cursor_move_left(&mut self) {
-        let new_x = self.cursor_position.x - 1;
-        if self.grid.item_at(new_x, self.cursor_position.y).is_some() {
-            self.cursor_position.x = new_x;
+    fn position_of(&self, value: &str) -> Option<grid::GridPosition> {
+        self.grid
+            .items_iter()
+            .find(|item| item.value == value)
+            .map(|item| item.position.clone())
+    }
+
+    // move the cursor to the item with this value, now or when it's added to the grid.
+    // this is forgotten if the user moves the cursor first.
+    pub fn set_initial_value(&mut self, value: String) {
+        match self.position_of(&value) {
+            Some(position) => {
+                self.cursor_position = position;
+                self.pending_initial_value = None;
+            }
+            None => self.pending_initial_value = Some(value),
         }
     }
 
-    pub fn cursor_move_down(&mut self) {
-        let new_y = self.cursor_position.y + 1;
-        if self.grid.item_at(self.cursor_position.x, new_y).is_some() {
-            self.cursor_position.y = new_y;
+    // replace the grid, keeping the cursor on the same item if it's still there.
+    //
+    // in a submenu, it's the outermost menu that's replaced. it'll be shown
+    // when we get back to it.
+    pub fn set_grid(&mut self, grid: grid::Grid) {
+        if let Some(root) = self.parents.first_mut() {
+            let cursor_value = root
+                .grid
+                .item_at(root.cursor_position.x, root.cursor_position.y)
+                .map(|item| item.value.clone());
+            root.cursor_position = cursor_value
+                .and_then(|value| grid.items_iter().find(|i| i.value == value))
+                .map_or_else(|| start_position(&grid), |i| i.position.clone());
+            root.grid = grid;
+            return;
         }
+
+        let cursor_value = self.get_selected_value().map(str::to_string);
+
+        self.grid = grid;
+        self.cursor_position = cursor_value
+            .and_then(|value| self.position_of(&value))
+            .unwrap_or_else(|| start_position(&self.grid));
+
+        if let Some(value) = self.pending_initial_value.take() {
+            self.set_initial_value(value);
+        }
+
+        self.needs_redraw = true;
     }
 
-    pub fn cursor_move_up(&mut self) {
-        let new_y = self.cursor_position.y - 1;
-        if self.grid.item_at(self.cursor_position.x, new_y).is_some() {
-            self.cursor_position.y = new_y;
+    // move the cursor, if there's an item at the new position.
+    fn cursor_move_to(&mut self, x: i8, y: i8) {
+        self.pending_initial_value = None;
+        if self.grid.item_at(x, y).is_some() {
+            self.cursor_position = grid::GridPosition::new(x, y);
+        }
+    }
+
+    // is there an item at (x, y)? this is in a wider type than a GridPosition,
+    // so that searches can look past the edge of the grid.
+    fn has_item(&self, x: i16, y: i16) -> bool {
+        let (rx, ry) = ((self.grid.width / 2) as i16, (self.grid.height / 2) as i16);
+        x.abs() <= rx && y.abs() <= ry && self.grid.item_at(x as i8, y as i8).is_some()
+    }
+
+    // the furthest that a search along a line needs to look.
+    fn reach(&self) -> i16 {
+        (self.grid.width + self.grid.height) as i16
+    }
+
+    // the step from a line in the direction (dx, dy) to the next line beside it.
+    //
+    // lines beside the cursor's are below it or to its right. diagonal lines
+    // are one step apart horizontally.
+    fn line_step(dx: i16, dy: i16) -> (i16, i16) {
+        if dx != 0 && dy != 0 {
+            (1, 0)
+        } else {
+            (dy.abs(), dx.abs())
         }
     }
 
+    // how far along the line `offset` lines beside the cursor the first item
+    // in the direction (dx, dy) is, counting only items ahead of the cursor.
+    fn distance_ahead(&self, dx: i16, dy: i16, offset: i16) -> Option<i16> {
+        let (lx, ly) = Self::line_step(dx, dy);
+        let (ox, oy) = (lx * offset, ly * offset);
+        let x = self.cursor_position.x as i16 + ox;
+        let y = self.cursor_position.y as i16 + oy;
+
+        // lines beside the cursor's can start outside the grid and cross into it
+        (1..=self.reach())
+            .filter(|n| (ox + dx * n) * dx + (oy + dy * n) * dy > 0)
+            .find(|n| self.has_item(x + dx * n, y + dy * n))
+    }
+
+    // the nearest item in the direction (dx, dy) along the cursor's line, or
+    // along the nearest line beside it that has one.
+    fn item_towards(&self, dx: i16, dy: i16, other_lines: bool) -> Option<grid::GridPosition> {
+        let offsets = if other_lines { 1..=self.reach() } else { 0..=0 };
+
+        let (distance, offset) = offsets.into_iter().find_map(|offset| {
+            // lines on either side are equally near, so take the closest item,
+            // or the one above or to the left.
+            [-offset, offset]
+                .into_iter()
+                .filter_map(|offset| Some((self.distance_ahead(dx, dy, offset)?, offset)))
+                .min()
+        })?;
+
+        let (lx, ly) = Self::line_step(dx, dy);
+        Some(grid::GridPosition::new(
+            (self.cursor_position.x as i16 + lx * offset + dx * distance) as i8,
+            (self.cursor_position.y as i16 + ly * offset + dy * distance) as i8,
+        ))
+    }
+
+    // the furthest item behind the cursor in its line, to wrap around to.
+    fn item_behind(&self, dx: i16, dy: i16) -> Option<grid::GridPosition> {
+        let (x, y) = (self.cursor_position.x as i16, self.cursor_position.y as i16);
+
+        (1..=self.reach())
+            .rev()
+            .map(|n| (x - dx * n, y - dy * n))
+            .find(|(x, y)| self.has_item(*x, *y))
+            .map(|(x, y)| grid::GridPosition::new(x as i8, y as i8))
+    }
+
+    // move the cursor one item in a direction, skipping over empty positions.
+    //
+    // at the end of its line the cursor wraps around to the other end if `wrap`
+    // is set. otherwise, or if there's nothing to wrap around to, it moves to
+    // the nearest item that's further in that direction on another line.
+    fn cursor_step(&mut self, dx: i16, dy: i16) {
+        let target = self
+            .item_towards(dx, dy, false)
+            .or_else(|| self.wrap.then(|| self.item_behind(dx, dy)).flatten())
+            .or_else(|| self.item_towards(dx, dy, true))
+            .unwrap_or_else(|| self.cursor_position.clone());
+        self.cursor_move_to(target.x, target.y);
+    }
+
+    pub fn cursor_move_left(&mut self) {
+        self.cursor_step(-1, 0);
+    }
+
+    pub fn cursor_move_down(&mut self) {
+        self.cursor_step(0, 1);
+    }
+
+    pub fn cursor_move_up(&mut self) {
+        self.cursor_step(0, -1);
+    }
+
     pub fn cursor_move_right(&mut self) {
-        let new_x = self.cursor_position.x + 1;
-        if self.grid.item_at(new_x, self.cursor_position.y).is_some() {
-            self.cursor_position.x = new_x;
+        self.cursor_step(1, 0);
+    }
+
+    pub fn cursor_move_up_left(&mut self) {
+        self.cursor_step(-1, -1);
+    }
+
+    pub fn cursor_move_up_right(&mut self) {
+        self.cursor_step(1, -1);
+    }
+
+    pub fn cursor_move_down_left(&mut self) {
+        self.cursor_step(-1, 1);
+    }
+
+    pub fn cursor_move_down_right(&mut self) {
+        self.cursor_step(1, 1);
+    }
+
+    // the section that the cursor is in, and how many items into it.
+    fn cursor_section(&self) -> Option<(&grid::Section, usize)> {
+        let section = self.grid.items.section_at(&self.cursor_position)?;
+        Some((section, section.index_of(&self.cursor_position)))
+    }
+
+    // move the cursor back to the centre of its section.
+    pub fn cursor_move_home(&mut self) {
+        let centre = self
+            .cursor_section()
+            .map_or(grid::GridPosition::new(0, 0), |(s, _)| s.centre.clone());
+        self.cursor_move_to(centre.x, centre.y);
+    }
+
+    // move the cursor to the item at `index`.
+    pub fn cursor_move_to_index(&mut self, index: usize) {
+        match self.grid.items.position_of(index) {
+            Some(position) => self.cursor_move_to(position.x, position.y),
+            None => self.pending_initial_value = None,
         }
     }
 
-    pub fn get_selected_value(&self) -> &str {
-        let maybe_grid_item = self
+    // move to the next item, spiralling out from the centre of each section in
+    // turn, and wrapping around to the first item after the last.
+    pub fn cursor_move_next(&mut self) {
+        let len = self.grid.items.len().max(1);
+        let index = self.grid.items.index_at(&self.cursor_position).unwrap_or(0);
+        self.cursor_move_to_index((index + 1) % len);
+    }
+
+    // move to the previous item, wrapping around to the last from the first.
+    pub fn cursor_move_previous(&mut self) {
+        let len = self.grid.items.len().max(1);
+        let index = self.grid.items.index_at(&self.cursor_position).unwrap_or(0);
+        self.cursor_move_to_index((index + len - 1) % len);
+    }
+
+    // move to the centre of the next section, wrapping around to the first after the last.
+    pub fn cursor_move_next_section(&mut self) {
+        self.cursor_move_by_sections(1);
+    }
+
+    // move to the centre of the previous section.
+    pub fn cursor_move_previous_section(&mut self) {
+        let sections = self.grid.items.sections().len();
+        self.cursor_move_by_sections(sections.saturating_sub(1));
+    }
+
+    fn cursor_move_by_sections(&mut self, by: usize) {
+        let sections = self.grid.items.sections();
+        let current = sections
+            .iter()
+            .position(|s| (s.left..=s.right).contains(&self.cursor_position.x))
+            .unwrap_or(0);
+        match sections.get((current + by) % sections.len().max(1)) {
+            Some(section) => {
+                let centre = section.centre.clone();
+                self.cursor_move_to(centre.x, centre.y);
+            }
+            None => self.pending_initial_value = None,
+        }
+    }
+
+    // move to the same place in the next ring out, or as near to it as there's an item.
+    pub fn cursor_move_outwards(&mut self) {
+        let centre = self.cursor_section().map(|(s, _)| s.centre.clone());
+        let ring = centre.map_or(0, |c| self.ring_around(&c));
+        self.cursor_move_to_ring(ring + 1);
+    }
+
+    // move to the same place in the next ring in.
+    pub fn cursor_move_inwards(&mut self) {
+        let centre = self.cursor_section().map(|(s, _)| s.centre.clone());
+        let ring = centre.map_or(0, |c| self.ring_around(&c));
+        self.cursor_move_to_ring(ring.saturating_sub(1));
+    }
+
+    // which ring around `centre` the cursor is in.
+    fn ring_around(&self, centre: &grid::GridPosition) -> usize {
+        spiral::ring_of(&grid::GridPosition::new(
+            self.cursor_position.x - centre.x,
+            self.cursor_position.y - centre.y,
+        ))
+    }
+
+    // move to a ring around the centre of the cursor's section.
+    fn cursor_move_to_ring(&mut self, ring: usize) {
+        let Some((section, n)) = self.cursor_section() else {
+            self.pending_initial_value = None;
+            return;
+        };
+
+        // how far around its ring the cursor is, from 0 to 1
+        let current = spiral::ring_of(&spiral::position_at(n));
+        let around = match current {
+            0 => 0.,
+            _ => {
+                let offset = n - spiral::total_positions_within(current - 1);
+                offset as f32 / spiral::positions_in_ring(current) as f32
+            }
+        };
+
+        let (first, count) = match ring {
+            0 => (0, 1),
+            _ => (
+                spiral::total_positions_within(ring - 1),
+                spiral::positions_in_ring(ring),
+            ),
+        };
+        let n = first + (around * count as f32).round() as usize % count;
+
+        // the outermost ring may not be full, in which case we go to whichever
+        // end of its items is nearer.
+        let Some(last) = section.len.checked_sub(1).filter(|l| *l >= first) else {
+            // there's nothing in that ring
+            self.pending_initial_value = None;
+            return;
+        };
+        let n = if n <= last {
+            n
+        } else if n - last <= first + count - n {
+            last
+        } else {
+            first
+        };
+
+        let position = section.position_at(n);
+        self.cursor_move_to(position.x, position.y);
+    }
+
+    // the value of the item under the cursor.
+    // the cursor only ever moves onto items, so this should always exist.
+    pub fn get_selected_value(&self) -> Option<&str> {
+        self.grid
+            .item_at(self.cursor_position.x, self.cursor_position.y)
+            .map(|grid_item| grid_item.value.as_str())
+    }
+
+    // show a submenu in place of the current grid.
+    pub fn enter(&mut self, grid: grid::Grid) {
+        let cursor_position = start_position(&grid);
+        let parent = std::mem::replace(&mut self.grid, grid);
+        self.parents.push(Parent {
+            grid: parent,
+            cursor_position: std::mem::replace(&mut self.cursor_position, cursor_position),
+        });
+
+        self.pending_initial_value = None;
+        self.query.clear();
+        self.needs_redraw = true;
+    }
+
+    // go back to the menu that the submenu was opened from, with the cursor where it was.
+    // returns false if we're not in a submenu.
+    pub fn leave(&mut self) -> bool {
+        let Some(parent) = self.parents.pop() else {
+            return false;
+        };
+        self.grid = parent.grid;
+        self.cursor_position = parent.cursor_position;
+
+        self.query.clear();
+        self.needs_redraw = true;
+        true
+    }
+
+    // carry on after an item has been chosen, back in the outermost menu.
+    pub fn resume(&mut self) {
+        while self.leave() {}
+        self.clear_query();
+        self.should_exit = false;
+        self.selected = None;
+    }
+
+    pub fn in_submenu(&self) -> bool {
+        !self.parents.is_empty()
+    }
+
+    // the submenu that choosing the item under the cursor opens, if any.
+    pub fn selected_submenu(&self) -> Option<&grid::Submenu> {
+        self.grid
+            .item_at(self.cursor_position.x, self.cursor_position.y)?
+            .submenu
+            .as_ref()
+    }
+
+    // choose the item under the cursor and shut down.
+    pub fn select(&mut self) {
+        match self
             .grid
-            .item_at(self.cursor_position.x, self.cursor_position.y);
-
-        match maybe_grid_item {
-            None => {
-                panic!(
-                    "could not select nonexistent item at x={}, y={}",
-                    self.cursor_position.x, self.cursor_position.y
-                )
+            .item_at(self.cursor_position.x, self.cursor_position.y)
+        {
+            Some(item) => {
+                self.selected = Some(Selection {
+                    value: item.value.clone(),
+                    display: item.display.clone(),
+                    index: item.input_index,
+                });
+                self.should_exit = true;
             }
-            Some(grid_item) => grid_item.value.as_str(),
+            None => log::error!(
+                "could not select nonexistent item at x={}, y={}",
+                self.cursor_position.x,
+                self.cursor_position.y
+            ),
+        }
+    }
+
+    // add typed text to the query, and move the cursor to the first item that
+    // it finds.
+    pub fn type_text(&mut self, text: &str) {
+        self.query.push_str(text);
+        self.cursor_move_to_query();
+        self.needs_redraw = true;
+    }
+
+    // remove the last character of the query. returns false if there's nothing to remove.
+    pub fn erase_query_char(&mut self) -> bool {
+        if self.query.pop().is_none() {
+            return false;
+        }
+        self.cursor_move_to_query();
+        self.needs_redraw = true;
+        true
+    }
+
+    // forget the query. returns false if there wasn't one.
+    pub fn clear_query(&mut self) -> bool {
+        if self.query.is_empty() {
+            return false;
+        }
+        self.query.clear();
+        self.needs_redraw = true;
+        true
+    }
+
+    // move the cursor to the first item (in the order they were given) whose
+    // displayed text starts with the query, ignoring case.
+    // the cursor stays where it is if there's no such item.
+    fn cursor_move_to_query(&mut self) {
+        if self.query.is_empty() {
+            return;
+        }
+        let query = self.query.to_lowercase();
+        let found = self
+            .grid
+            .items_iter()
+            .filter(|item| item.display.to_lowercase().starts_with(&query))
+            .min_by_key(|item| item.index)
+            .map(|item| item.position.clone());
+        if let Some(position) = found {
+            self.cursor_move_to(position.x, position.y);
         }
     }
 
@@ -75,3 +514,511 @@ impl State {
         self.rendered_cursor_position != self.cursor_position
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+
+    fn grid() -> grid::Grid {
+        let choices: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|v| grid::Choice::new(v.to_string()))
+            .collect();
+        grid::Grid::new(&choices).unwrap()
+    }
+
+    // a grid of between 1 and max_items() items, with the cursor on one of them.
+    fn state_with(items: u8, cursor: u8, wrap: bool) -> State {
+        let items = items as usize % grid::max_items() + 1;
+        let choices: Vec<_> = (0..items)
+            .map(|i| grid::Choice::new(i.to_string()))
+            .collect();
+
+        let mut state = State::new(grid::Grid::new(&choices).unwrap(), None);
+        state.wrap = wrap;
+        state.cursor_position = spiral::position_at(cursor as usize % items);
+        state
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum Direction {
+        Left,
+        Down,
+        Up,
+        Right,
+        UpLeft,
+        UpRight,
+        DownLeft,
+        DownRight,
+    }
+
+    const DIRECTIONS: [Direction; 8] = [
+        Direction::Left,
+        Direction::Down,
+        Direction::Up,
+        Direction::Right,
+        Direction::UpLeft,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::DownRight,
+    ];
+
+    impl Direction {
+        fn delta(self) -> (i8, i8) {
+            match self {
+                Direction::Left => (-1, 0),
+                Direction::Down => (0, 1),
+                Direction::Up => (0, -1),
+                Direction::Right => (1, 0),
+                Direction::UpLeft => (-1, -1),
+                Direction::UpRight => (1, -1),
+                Direction::DownLeft => (-1, 1),
+                Direction::DownRight => (1, 1),
+            }
+        }
+
+        fn opposite(self) -> Direction {
+            match self {
+                Direction::Left => Direction::Right,
+                Direction::Down => Direction::Up,
+                Direction::Up => Direction::Down,
+                Direction::Right => Direction::Left,
+                Direction::UpLeft => Direction::DownRight,
+                Direction::UpRight => Direction::DownLeft,
+                Direction::DownLeft => Direction::UpRight,
+                Direction::DownRight => Direction::UpLeft,
+            }
+        }
+
+        fn apply(self, state: &mut State) {
+            match self {
+                Direction::Left => state.cursor_move_left(),
+                Direction::Down => state.cursor_move_down(),
+                Direction::Up => state.cursor_move_up(),
+                Direction::Right => state.cursor_move_right(),
+                Direction::UpLeft => state.cursor_move_up_left(),
+                Direction::UpRight => state.cursor_move_up_right(),
+                Direction::DownLeft => state.cursor_move_down_left(),
+                Direction::DownRight => state.cursor_move_down_right(),
+            }
+        }
+    }
+
+    impl Arbitrary for Direction {
+        fn arbitrary(g: &mut Gen) -> Direction {
+            *g.choose(&DIRECTIONS).unwrap()
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Move {
+        Step(Direction),
+        Home,
+        Next,
+        Previous,
+        Outwards,
+        Inwards,
+        NextSection,
+        PreviousSection,
+    }
+
+    impl Arbitrary for Move {
+        fn arbitrary(g: &mut Gen) -> Move {
+            match u8::arbitrary(g) % 8 {
+                0 => Move::Step(Direction::arbitrary(g)),
+                1 => Move::Home,
+                2 => Move::Next,
+                3 => Move::Previous,
+                4 => Move::Outwards,
+                5 => Move::Inwards,
+                6 => Move::NextSection,
+                _ => Move::PreviousSection,
+            }
+        }
+    }
+
+    impl Move {
+        fn apply(&self, state: &mut State) {
+            match self {
+                Move::Step(direction) => direction.apply(state),
+                Move::Home => state.cursor_move_home(),
+                Move::Next => state.cursor_move_next(),
+                Move::Previous => state.cursor_move_previous(),
+                Move::Outwards => state.cursor_move_outwards(),
+                Move::Inwards => state.cursor_move_inwards(),
+                Move::NextSection => state.cursor_move_next_section(),
+                Move::PreviousSection => state.cursor_move_previous_section(),
+            }
+        }
+    }
+
+    // items divided between up to `groups` sections, each with its own spiral.
+    fn sectioned_state(items: u8, groups: u8, wrap: bool) -> State {
+        let items = items as usize % grid::max_items() + 1;
+        let groups = groups as usize % 5 + 1;
+        let choices: Vec<_> = (0..items)
+            .map(|i| grid::Choice {
+                group: Some((i % groups).to_string()),
+                ..grid::Choice::new(i.to_string())
+            })
+            .collect();
+
+        let mut state = State::new(grid::Grid::new(&choices).unwrap(), None);
+        state.wrap = wrap;
+        state
+    }
+
+    #[quickcheck]
+    fn prop_cursor_stays_on_items(items: u8, wrap: bool, moves: Vec<Move>) -> bool {
+        let mut state = state_with(items, 0, wrap);
+        moves.into_iter().all(|m| {
+            m.apply(&mut state);
+            state.get_selected_value().is_some()
+        })
+    }
+
+    #[quickcheck]
+    fn prop_cursor_stays_on_sectioned_items(
+        items: u8,
+        groups: u8,
+        wrap: bool,
+        moves: Vec<Move>,
+    ) -> bool {
+        let mut state = sectioned_state(items, groups, wrap);
+        state.get_selected_value().is_some()
+            && moves.into_iter().all(|m| {
+                m.apply(&mut state);
+                state.get_selected_value().is_some()
+            })
+    }
+
+    #[quickcheck]
+    fn prop_tab_visits_every_section_in_order(items: u8, groups: u8) -> bool {
+        let mut state = sectioned_state(items, groups, false);
+        let mut values = vec![];
+        for _ in 0..state.grid.items.len() {
+            values.push(state.get_selected_value().unwrap().to_string());
+            state.cursor_move_next();
+        }
+
+        let expected: Vec<_> = state.grid.items_iter().map(|i| i.value.clone()).collect();
+        values == expected && state.cursor_position == start_position(&state.grid)
+    }
+
+    #[quickcheck]
+    fn prop_step_moves_in_direction(items: u8, cursor: u8, direction: Direction) -> bool {
+        let mut state = state_with(items, cursor, false);
+        let from = state.cursor_position.clone();
+        direction.apply(&mut state);
+
+        let (dx, dy) = direction.delta();
+        let to = &state.cursor_position;
+        *to == from || (to.x - from.x) * dx + (to.y - from.y) * dy > 0
+    }
+
+    #[quickcheck]
+    fn prop_step_along_line_reverses(
+        items: u8,
+        cursor: u8,
+        wrap: bool,
+        direction: Direction,
+    ) -> bool {
+        let mut state = state_with(items, cursor, wrap);
+        let from = state.cursor_position.clone();
+        direction.apply(&mut state);
+        let to = state.cursor_position.clone();
+
+        // only a move along the cursor's line, without wrapping, can be undone
+        let (dx, dy) = direction.delta();
+        let along_line = (to.x - from.x) * dy == (to.y - from.y) * dx;
+        let forwards = (to.x - from.x) * dx + (to.y - from.y) * dy > 0;
+        if to == from || !along_line || !forwards {
+            return true;
+        }
+
+        direction.opposite().apply(&mut state);
+        state.cursor_position == from
+    }
+
+    #[quickcheck]
+    fn prop_next_and_previous(items: u8, cursor: u8) -> bool {
+        let mut state = state_with(items, cursor, false);
+        let from = state.cursor_position.clone();
+
+        state.cursor_move_next();
+        state.cursor_move_previous();
+        let reversed = state.cursor_position == from;
+
+        // tabbing through every item comes back around
+        for _ in 0..state.grid.items.len() {
+            state.cursor_move_next();
+        }
+        reversed && state.cursor_position == from
+    }
+
+    #[quickcheck]
+    fn prop_rings(items: u8, cursor: u8) -> bool {
+        let mut state = state_with(items, cursor, false);
+        let ring = spiral::ring_of(&state.cursor_position);
+        let last_ring = spiral::ring_of(&spiral::position_at(state.grid.items.len() - 1));
+
+        state.cursor_move_outwards();
+        let outwards = spiral::ring_of(&state.cursor_position) == (ring + 1).min(last_ring);
+
+        let mut state = state_with(items, cursor, false);
+        state.cursor_move_inwards();
+        let inwards = spiral::ring_of(&state.cursor_position) == ring.saturating_sub(1);
+
+        state.cursor_move_home();
+        outwards && inwards && state.cursor_position == grid::GridPosition::new(0, 0)
+    }
+
+    #[test]
+    fn test_neighbours_in_every_ring() {
+        // a full grid, with every ring complete
+        let mut state = state_with(grid::max_items() as u8 - 1, 0, false);
+        let positions: Vec<_> = state
+            .grid
+            .items_iter()
+            .map(|i| i.position.clone())
+            .collect();
+
+        for from in positions {
+            for direction in DIRECTIONS {
+                state.cursor_position = from.clone();
+                direction.apply(&mut state);
+
+                let (dx, dy) = direction.delta();
+                let (x, y) = (from.x + dx, from.y + dy);
+                if state.grid.item_at(x, y).is_some() {
+                    assert_eq!(
+                        grid::GridPosition::new(x, y),
+                        state.cursor_position,
+                        "{direction:?} from {from:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagonal_off_line() {
+        // the last ring is half full, so there's nothing directly up and to the
+        // left of its first item
+        let mut state = state_with(9, 5, false);
+        assert_eq!(grid::GridPosition::new(0, 2), state.cursor_position);
+        assert!(state.grid.item_at(-1, 1).is_none());
+
+        state.cursor_move_up_left();
+        assert_eq!(grid::GridPosition::new(0, 1), state.cursor_position);
+
+        // nothing at all is down and to the right of the right-hand tip
+        state.cursor_move_to(2, 0);
+        state.cursor_move_down_right();
+        assert_eq!(grid::GridPosition::new(2, 0), state.cursor_position);
+    }
+
+    #[test]
+    fn test_step_off_line() {
+        // 13 items fill a 5x5 diamond. the bottom of it has nothing beside it.
+        let mut state = state_with(12, 5, false);
+        assert_eq!(grid::GridPosition::new(0, 2), state.cursor_position);
+
+        state.cursor_move_right();
+        assert_eq!(grid::GridPosition::new(1, 1), state.cursor_position);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut state = state_with(12, 7, true);
+        assert_eq!(grid::GridPosition::new(2, 0), state.cursor_position);
+
+        state.cursor_move_right();
+        assert_eq!(grid::GridPosition::new(-2, 0), state.cursor_position);
+
+        state.cursor_move_to(0, 2);
+        state.cursor_move_down();
+        assert_eq!(grid::GridPosition::new(0, -2), state.cursor_position);
+
+        // there's nothing else on this line to wrap around to
+        state.cursor_move_to(0, 2);
+        state.cursor_move_left();
+        assert_eq!(grid::GridPosition::new(-1, 1), state.cursor_position);
+    }
+
+    #[test]
+    fn test_sections() {
+        // sections "a" (3 items), "b" (1) and "c" (6), 3, 1 and 5 columns wide
+        let choices: Vec<_> = [("a", 3), ("b", 1), ("c", 6)]
+            .into_iter()
+            .flat_map(|(group, n)| {
+                (0..n).map(move |i| grid::Choice {
+                    group: Some(group.to_string()),
+                    ..grid::Choice::new(format!("{group}{i}"))
+                })
+            })
+            .collect();
+        let mut state = State::new(grid::Grid::new(&choices).unwrap(), None);
+        assert_eq!(Some("a0"), state.get_selected_value());
+
+        // the cursor crosses the gaps between sections, and "b" isn't on its line
+        state.cursor_move_right();
+        assert_eq!(Some("a2"), state.get_selected_value());
+        state.cursor_move_right();
+        assert_eq!(Some("c3"), state.get_selected_value());
+        state.cursor_move_left();
+        assert_eq!(Some("a2"), state.get_selected_value());
+        state.cursor_move_up_right();
+        assert_eq!(Some("b0"), state.get_selected_value());
+
+        // home and rings are relative to the cursor's section
+        state.cursor_move_home();
+        assert_eq!(Some("b0"), state.get_selected_value());
+        state.cursor_move_to(4, 0);
+        state.cursor_move_home();
+        assert_eq!(Some("c0"), state.get_selected_value());
+        state.cursor_move_outwards();
+        state.cursor_move_outwards();
+        assert_eq!(Some("c5"), state.get_selected_value());
+        state.cursor_move_inwards();
+        assert_eq!(Some("c1"), state.get_selected_value());
+
+        // jumping between sections wraps around
+        state.cursor_move_next_section();
+        assert_eq!(Some("a0"), state.get_selected_value());
+        state.cursor_move_previous_section();
+        assert_eq!(Some("c0"), state.get_selected_value());
+        state.cursor_move_previous_section();
+        assert_eq!(Some("b0"), state.get_selected_value());
+    }
+
+    #[test]
+    fn test_submenus() {
+        let choices = vec![
+            grid::Choice::new("a".to_string()),
+            grid::Choice {
+                children: vec![
+                    grid::Choice::new("b1".to_string()),
+                    grid::Choice::new("b2".to_string()),
+                ],
+                ..grid::Choice::new("b".to_string())
+            },
+        ];
+        let mut state = State::new(grid::Grid::new(&choices).unwrap(), Some("b"));
+        let Some(grid::Submenu::Children(children)) = state.selected_submenu().cloned() else {
+            panic!("b should be a submenu");
+        };
+
+        state.enter(grid::Grid::new(&children).unwrap());
+        assert!(state.in_submenu());
+        assert_eq!(Some("b1"), state.get_selected_value());
+        assert!(state.selected_submenu().is_none());
+        state.cursor_move_next();
+
+        // more choices arriving replace the outer menu, out of sight
+        let mut more = choices.clone();
+        more.insert(0, grid::Choice::new("z".to_string()));
+        state.set_grid(grid::Grid::new(&more).unwrap());
+        assert_eq!(Some("b2"), state.get_selected_value());
+
+        assert!(state.leave());
+        assert_eq!(3, state.grid.items.len());
+        assert_eq!(Some("b"), state.get_selected_value());
+        assert!(!state.leave());
+
+        // carrying on after a choice goes back to the outermost menu
+        state.enter(grid::Grid::new(&children).unwrap());
+        state.select();
+        assert_eq!(
+            Some(Selection {
+                value: "b1".to_string(),
+                display: "b1".to_string(),
+                index: 0,
+            }),
+            state.selected
+        );
+        state.resume();
+        assert!(!state.in_submenu());
+        assert!(!state.should_exit);
+        assert_eq!(None, state.selected);
+    }
+
+    #[test]
+    fn test_query() {
+        let choices: Vec<_> = ["東京", "大阪", "京都", "Kyoto", "東北"]
+            .iter()
+            .map(|v| grid::Choice::new(v.to_string()))
+            .collect();
+        let mut state = State::new(grid::Grid::new(&choices).unwrap(), None);
+
+        state.type_text("東");
+        assert_eq!(Some("東京"), state.get_selected_value());
+        state.type_text("北");
+        assert_eq!(Some("東北"), state.get_selected_value());
+
+        // nothing starts with this, so the cursor stays put
+        state.type_text("x");
+        assert_eq!("東北x", state.query);
+        assert_eq!(Some("東北"), state.get_selected_value());
+
+        assert!(state.erase_query_char());
+        assert!(state.erase_query_char());
+        assert_eq!(Some("東京"), state.get_selected_value());
+
+        assert!(state.clear_query());
+        assert!(!state.clear_query());
+        assert!(!state.erase_query_char());
+
+        state.type_text("ky");
+        assert_eq!(Some("Kyoto"), state.get_selected_value());
+    }
+
+    #[test]
+    fn test_initial_value() {
+        let mut state = State::new(grid(), Some("c"));
+        assert_ne!(grid::GridPosition::new(0, 0), state.cursor_position);
+
+        state.select();
+        assert_eq!(Some("c"), state.selected.map(|s| s.value).as_deref());
+    }
+
+    #[test]
+    fn test_set_grid() {
+        let mut state = State::new(grid(), Some("z"));
+        state.cursor_move_to(1, 0);
+        let value = state.get_selected_value().unwrap().to_string();
+
+        // the user moved the cursor, so "z" won't be preselected when it arrives.
+        let choices: Vec<_> = ["x", "a", "b", "c", "z"]
+            .iter()
+            .map(|v| grid::Choice::new(v.to_string()))
+            .collect();
+        state.set_grid(grid::Grid::new(&choices).unwrap());
+
+        assert_eq!(Some(value.as_str()), state.get_selected_value());
+    }
+
+    #[test]
+    fn test_pending_initial_value() {
+        let mut state = State::new(grid(), Some("z"));
+
+        let choices: Vec<_> = ["a", "b", "c", "z"]
+            .iter()
+            .map(|v| grid::Choice::new(v.to_string()))
+            .collect();
+        state.set_grid(grid::Grid::new(&choices).unwrap());
+
+        assert_eq!(Some("z"), state.get_selected_value());
+    }
+
+    #[test]
+    fn test_missing_initial_value() {
+        let mut state = State::new(grid(), Some("z"));
+        assert_eq!(grid::GridPosition::new(0, 0), state.cursor_position);
+
+        state.select();
+        assert_eq!(Some("a"), state.selected.map(|s| s.value).as_deref());
+    }
+}
@@ -0,0 +1,206 @@
+// a minimal stand-in for a compositor, for testing our Wayland protocol clients.
+//
+// the test provides the server state, which implements the server side of
+// whichever protocols are being tested.
+
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use wayland_protocols_wlr::layer_shell::v1::server::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
+use wayland_server::backend::ClientData;
+use wayland_server::protocol::{
+    wl_buffer, wl_compositor, wl_region, wl_shm, wl_shm_pool, wl_surface,
+};
+use wayland_server::{Client, DataInit, Dispatch, Display, DisplayHandle, GlobalDispatch, New};
+
+struct TestClient;
+
+impl ClientData for TestClient {}
+
+pub struct TestCompositor<S> {
+    thread: JoinHandle<S>,
+    stop: Arc<AtomicBool>,
+}
+
+impl<S: Send + 'static> TestCompositor<S> {
+    // start serving a single client, returning the client's connection.
+    //
+    // `setup` should create the globals that the client will bind.
+    pub fn spawn(
+        mut state: S,
+        setup: impl FnOnce(&DisplayHandle) + Send + 'static,
+    ) -> (wayland_client::Connection, TestCompositor<S>) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut display = Display::<S>::new().unwrap();
+                setup(&display.handle());
+                display
+                    .handle()
+                    .insert_client(server_stream, Arc::new(TestClient))
+                    .unwrap();
+
+                while !stop.load(Ordering::Relaxed) {
+                    display.dispatch_clients(&mut state).unwrap();
+                    display.flush_clients().unwrap();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+
+                state
+            }
+        });
+
+        let conn = wayland_client::Connection::from_socket(client_stream).unwrap();
+        (conn, TestCompositor { thread, stop })
+    }
+
+    // stop serving, returning the final server state.
+    pub fn stop(self) -> S {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.join().unwrap()
+    }
+}
+
+// a compositor that can show layer surfaces, for testing windows.
+//
+// each layer surface is closed as soon as it's created, so that windows exit
+// straight away without being drawn.
+#[derive(Default)]
+pub struct Shell {
+    // how many layer surfaces have been created
+    pub layers: usize,
+}
+
+impl Shell {
+    pub fn spawn() -> (wayland_client::Connection, TestCompositor<Shell>) {
+        TestCompositor::spawn(Shell::default(), |dh| {
+            dh.create_global::<Shell, wl_compositor::WlCompositor, ()>(4, ());
+            dh.create_global::<Shell, wl_shm::WlShm, ()>(1, ());
+            dh.create_global::<Shell, zwlr_layer_shell_v1::ZwlrLayerShellV1, ()>(4, ());
+        })
+    }
+}
+
+// globals that just need to be bound
+macro_rules! bind {
+    ($($interface:ty),*) => {$(
+        impl GlobalDispatch<$interface, ()> for Shell {
+            fn bind(
+                _: &mut Self,
+                _: &DisplayHandle,
+                _: &Client,
+                resource: New<$interface>,
+                _: &(),
+                data_init: &mut DataInit<'_, Self>,
+            ) {
+                data_init.init(resource, ());
+            }
+        }
+    )*};
+}
+
+// objects whose requests don't matter to the tests
+macro_rules! ignore_requests {
+    ($($module:ident :: $interface:ident),*) => {$(
+        impl Dispatch<$module::$interface, ()> for Shell {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &$module::$interface,
+                _: $module::Request,
+                _: &(),
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+            }
+        }
+    )*};
+}
+
+bind!(
+    wl_compositor::WlCompositor,
+    wl_shm::WlShm,
+    zwlr_layer_shell_v1::ZwlrLayerShellV1
+);
+ignore_requests!(
+    wl_surface::WlSurface,
+    wl_region::WlRegion,
+    wl_buffer::WlBuffer,
+    zwlr_layer_surface_v1::ZwlrLayerSurfaceV1
+);
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for Shell {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &wl_compositor::WlCompositor,
+        request: wl_compositor::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_compositor::Request::CreateSurface { id } => {
+                data_init.init(id, ());
+            }
+            wl_compositor::Request::CreateRegion { id } => {
+                data_init.init(id, ());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for Shell {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &wl_shm::WlShm,
+        request: wl_shm::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_shm::Request::CreatePool { id, .. } = request {
+            data_init.init(id, ());
+        }
+    }
+}
+
+impl Dispatch<wl_shm_pool::WlShmPool, ()> for Shell {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &wl_shm_pool::WlShmPool,
+        request: wl_shm_pool::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_shm_pool::Request::CreateBuffer { id, .. } = request {
+            data_init.init(id, ());
+        }
+    }
+}
+
+impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for Shell {
+    fn request(
+        state: &mut Self,
+        _: &Client,
+        _: &zwlr_layer_shell_v1::ZwlrLayerShellV1,
+        request: zwlr_layer_shell_v1::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_layer_shell_v1::Request::GetLayerSurface { id, .. } = request {
+            state.layers += 1;
+            data_init.init(id, ()).closed();
+        }
+    }
+}
@@ -1,7 +1,50 @@
-use crate::{colour, layout, render};
+use crate::{colour, config, layout, render};
 
-use cosmic_text::{Align, Attrs, Buffer, FontSystem, Metrics, Shaping, SwashCache};
+use cosmic_text::{Align, Attrs, Buffer, FontSystem, Metrics, Shaping, SwashCache, Wrap};
 use raqote::{DrawOptions, Source};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const ELLIPSIS: char = '…';
+
+// what to do with text that's too large to fit in its item.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum TextOverflow {
+    // truncate the text to a single line, ending in "…".
+    // paths are truncated in the middle, so that the file name stays visible.
+    Ellipsis,
+    // wrap the text over as many lines as fit (or text_max_lines).
+    #[default]
+    Wrap,
+    // reduce the font size until the text fits on one line, down to min_font_size.
+    // if it still doesn't fit it's truncated like Ellipsis.
+    Shrink,
+}
+
+// the settings that control how text is laid out.
+#[derive(Clone)]
+pub struct Style {
+    pub font_name: Option<String>,
+    pub font_size: f32,
+    pub min_font_size: f32,
+    pub overflow: TextOverflow,
+    pub max_lines: Option<usize>,
+}
+
+impl From<&config::Config> for Style {
+    fn from(config: &config::Config) -> Self {
+        Style {
+            font_name: config.font_name.clone(),
+            font_size: config.font_size,
+            min_font_size: config.min_font_size.min(config.font_size),
+            overflow: config.text_overflow,
+            max_lines: config.text_max_lines,
+        }
+    }
+}
 
 fn make_buffer(
     text: &str,
@@ -10,6 +53,7 @@ fn make_buffer(
     size: f32,
     scale: f32,
     space: &layout::Space,
+    wrap: Wrap,
 ) -> Buffer {
     // Text metrics indicate the font size and line height of a buffer
     let metrics = Metrics::new(size, size).scale(scale);
@@ -19,7 +63,19 @@ fn make_buffer(
 
     // Set a size for the text buffer, in pixels
     buffer.set_size(font_system, Some(space.width), Some(space.height));
+    buffer.set_wrap(font_system, wrap);
+
+    set_buffer_text(&mut buffer, font_system, text, font_name);
 
+    buffer
+}
+
+fn set_buffer_text(
+    buffer: &mut Buffer,
+    font_system: &mut FontSystem,
+    text: &str,
+    font_name: Option<&str>,
+) {
     // Attributes indicate what font to choose
     let mut attrs = Attrs::new();
     if let Some(font_name) = font_name {
@@ -34,8 +90,148 @@ fn make_buffer(
         Shaping::Advanced,
         Some(Align::Center),
     );
+}
 
+// the width of the widest line in the buffer.
+fn buffer_width(buffer: &Buffer) -> f32 {
     buffer
+        .layout_runs()
+        .map(|run| run.line_w)
+        .fold(0., f32::max)
+}
+
+// the number of lines we'll draw from the buffer.
+fn visible_lines(buffer: &Buffer, style: &Style, space: &layout::Space) -> usize {
+    let run_count = buffer.layout_runs().count();
+    match style.overflow {
+        TextOverflow::Wrap => {
+            // always show at least one line, even if it's taller than the space.
+            let fitting = (space.height / buffer.metrics().line_height).floor() as usize;
+            let max_lines = style.max_lines.unwrap_or(usize::MAX);
+            run_count.min(fitting.max(1)).min(max_lines.max(1))
+        }
+        TextOverflow::Ellipsis | TextOverflow::Shrink => run_count.min(1),
+    }
+}
+
+// `text` with all but `keep` characters replaced by an ellipsis.
+//
+// if `middle` is set the characters are kept from both ends of the text,
+// otherwise they're kept from the start.
+fn ellipsize(text: &str, keep: usize, middle: bool) -> String {
+    let len = text.chars().count();
+    if keep >= len {
+        return text.to_string();
+    }
+
+    let (head, tail) = if middle {
+        (keep / 2, keep - keep / 2)
+    } else {
+        (keep, 0)
+    };
+
+    text.chars()
+        .take(head)
+        .chain(std::iter::once(ELLIPSIS))
+        .chain(text.chars().skip(len - tail))
+        .collect()
+}
+
+// paths get truncated in the middle so that both the root and the file name are visible.
+fn looks_like_path(text: &str) -> bool {
+    text.contains('/')
+}
+
+// replace the buffer's text with the longest ellipsized version of `text` that fits in `width`.
+fn ellipsize_to_fit(
+    buffer: &mut Buffer,
+    font_system: &mut FontSystem,
+    text: &str,
+    font_name: Option<&str>,
+    width: f32,
+) {
+    if buffer_width(buffer) <= width {
+        return;
+    }
+
+    let middle = looks_like_path(text);
+
+    // binary search for the largest number of characters we can keep.
+    let (mut fits, mut too_long) = (0, text.chars().count());
+    while too_long - fits > 1 {
+        let keep = (fits + too_long) / 2;
+        set_buffer_text(
+            buffer,
+            font_system,
+            &ellipsize(text, keep, middle),
+            font_name,
+        );
+        if buffer_width(buffer) <= width {
+            fits = keep;
+        } else {
+            too_long = keep;
+        }
+    }
+
+    set_buffer_text(
+        buffer,
+        font_system,
+        &ellipsize(text, fits, middle),
+        font_name,
+    );
+}
+
+// shape the text according to the style's overflow handling.
+fn layout_text(
+    text: &str,
+    style: &Style,
+    font_system: &mut FontSystem,
+    scale: f32,
+    space: &layout::Space,
+) -> Buffer {
+    let font_name = style.font_name.as_deref();
+
+    match style.overflow {
+        TextOverflow::Wrap => make_buffer(
+            text,
+            font_system,
+            font_name,
+            style.font_size,
+            scale,
+            space,
+            Wrap::WordOrGlyph,
+        ),
+        TextOverflow::Ellipsis => {
+            let mut buffer = make_buffer(
+                text,
+                font_system,
+                font_name,
+                style.font_size,
+                scale,
+                space,
+                Wrap::None,
+            );
+            ellipsize_to_fit(&mut buffer, font_system, text, font_name, space.width);
+            buffer
+        }
+        TextOverflow::Shrink => {
+            let mut size = style.font_size;
+            let mut buffer =
+                make_buffer(text, font_system, font_name, size, scale, space, Wrap::None);
+
+            let fits = |buffer: &Buffer| {
+                buffer_width(buffer) <= space.width && buffer.metrics().line_height <= space.height
+            };
+
+            while !fits(&buffer) && size > style.min_font_size {
+                size = (size - 1.).max(style.min_font_size);
+                buffer.set_metrics(font_system, Metrics::new(size, size).scale(scale));
+            }
+
+            ellipsize_to_fit(&mut buffer, font_system, text, font_name, space.width);
+            buffer
+        }
+    }
 }
 
 struct RenderedText {
@@ -54,46 +250,51 @@ impl RenderedText {
     }
 }
 
-// render the text into a new image buffer.
-fn render_text_centred(
-    text: &str,
-    font_name: Option<&str>,
-    font_size: f32,
+// render the shaped text into a new image buffer.
+fn render_buffer_centred(
+    buffer: &Buffer,
+    style: &Style,
     font_system: &mut FontSystem,
     swash_cache: &mut SwashCache,
-    scale: f32,
     colour: &colour::Colour,
     space: &layout::Space,
 ) -> RenderedText {
     let mut dt = raqote::DrawTarget::new(space.width as i32, space.height as i32);
 
-    let buffer = make_buffer(text, font_system, font_name, font_size, scale, space);
-
-    let run_count = buffer.layout_runs().count();
-    let text_height = buffer.metrics().line_height * run_count as f32;
+    let line_count = visible_lines(buffer, style, space);
+    let text_height = buffer.metrics().line_height * line_count as f32;
 
     // centre the text vertically.
     // (cosmic text already centres it horizontally)
     let x_offset = 0.;
     let y_offset = (space.height - text_height) / 2.;
 
-    buffer.draw(
-        font_system,
-        swash_cache,
-        colour.as_cosmic(),
-        |x, y, w, h, colour| {
-            let (r, g, b, a) = colour.as_rgba_tuple();
-            let source = Source::Solid(raqote::SolidSource::from_unpremultiplied_argb(a, r, g, b));
-            dt.fill_rect(
-                x_offset + x as f32,
-                y_offset + y as f32,
-                w as f32,
-                h as f32,
-                &source,
-                &DrawOptions::new(),
+    // this is Buffer::draw, limited to the lines that we want to show.
+    for run in buffer.layout_runs().take(line_count) {
+        for glyph in run.glyphs {
+            let physical_glyph = glyph.physical((0., 0.), 1.0);
+            let glyph_colour = glyph.color_opt.unwrap_or(colour.as_cosmic());
+
+            swash_cache.with_pixels(
+                font_system,
+                physical_glyph.cache_key,
+                glyph_colour,
+                |x, y, colour| {
+                    let (r, g, b, a) = colour.as_rgba_tuple();
+                    let source =
+                        Source::Solid(raqote::SolidSource::from_unpremultiplied_argb(a, r, g, b));
+                    dt.fill_rect(
+                        x_offset + (physical_glyph.x + x) as f32,
+                        y_offset + (run.line_y as i32 + physical_glyph.y + y) as f32,
+                        1.,
+                        1.,
+                        &source,
+                        &DrawOptions::new(),
+                    );
+                },
             );
-        },
-    );
+        }
+    }
 
     RenderedText {
         width: space.width as i32,
@@ -102,21 +303,63 @@ fn render_text_centred(
     }
 }
 
+// what the shaped text depends on, apart from the text and its style.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct LayoutKey {
+    scale: u32,
+    width: u32,
+    height: u32,
+}
+
+impl LayoutKey {
+    fn new(scale: f32, space: &layout::Space) -> LayoutKey {
+        LayoutKey {
+            scale: scale.to_bits(),
+            width: space.width.to_bits(),
+            height: space.height.to_bits(),
+        }
+    }
+}
+
+// shaping and rasterising text is the slowest part of drawing an item, so we
+// keep the results until the item's size or scale changes.
+#[derive(Default)]
+struct Cache {
+    layout: Option<(LayoutKey, Buffer)>,
+    // one image per colour that the text has been drawn in (e.g. selected and not)
+    images: HashMap<(LayoutKey, colour::Colour), RenderedText>,
+}
+
 pub struct Text {
     text: String,
-    font_name: Option<String>,
-    font_size: f32,
+    style: Style,
+    cache: RefCell<Cache>,
 }
 
 impl Text {
-    pub fn new(text: String, font_name: Option<String>, size: f32) -> Text {
+    pub fn new(text: String, style: Style) -> Text {
         Text {
             text,
-            font_name,
-            font_size: size,
+            style,
+            cache: RefCell::default(),
         }
     }
 
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    // forget the shaped and rendered text, e.g. because the scale has changed.
+    pub fn clear_cache(&self) {
+        *self.cache.borrow_mut() = Cache::default();
+    }
+
+    #[cfg(test)]
+    pub fn is_cached(&self) -> bool {
+        self.cache.borrow().layout.is_some()
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn render_centred(
         &self,
         dt: &mut render::DrawTarget,
@@ -134,16 +377,24 @@ impl Text {
         //
         // this could mabye make sense if the small image fits into cache better, but that's
         // total speculation.
-        let rendered_text = render_text_centred(
-            self.text.as_str(),
-            self.font_name.as_deref(),
-            self.font_size,
-            font_system,
-            swash_cache,
-            scale,
-            colour,
-            space,
-        );
+        let key = LayoutKey::new(scale, space);
+        let mut cache = self.cache.borrow_mut();
+        let Cache { layout, images } = &mut *cache;
+
+        // the item has changed size, so nothing we have is any use.
+        if layout.as_ref().is_some_and(|(k, _)| *k != key) {
+            *layout = None;
+            images.clear();
+        }
+
+        let rendered_text = images.entry((key, colour.clone())).or_insert_with(|| {
+            let (_, buffer) = layout.get_or_insert_with(|| {
+                let buffer = layout_text(&self.text, &self.style, font_system, scale, space);
+                (key, buffer)
+            });
+
+            render_buffer_centred(buffer, &self.style, font_system, swash_cache, colour, space)
+        });
 
         dt.draw_image_at(
             position.x,
@@ -153,3 +404,86 @@ impl Text {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ellipsize_end() {
+        assert_eq!("firefox", ellipsize("firefox", 7, false));
+        assert_eq!("firefox", ellipsize("firefox", 10, false));
+        assert_eq!("fire…", ellipsize("firefox", 4, false));
+        assert_eq!("…", ellipsize("firefox", 0, false));
+    }
+
+    #[test]
+    fn test_ellipsize_middle() {
+        assert_eq!("/ho…n.rs", ellipsize("/home/bct/src/main.rs", 7, true));
+        assert_eq!("/…rs", ellipsize("/home/bct/src/main.rs", 3, true));
+    }
+
+    #[test]
+    fn test_ellipsize_multibyte() {
+        assert_eq!("日本…", ellipsize("日本語のテキスト", 2, false));
+    }
+
+    #[test]
+    fn test_render_cache() {
+        // a font of our own, so that this doesn't depend on what's installed
+        let font =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fonts/Tuffy.ttf");
+        let mut db = crate::fonts::database_from_paths([&font]);
+        assert_eq!(1, db.len());
+        db.set_sans_serif_family("Tuffy");
+        let mut font_system = FontSystem::new_with_locale_and_db("en-US".to_string(), db);
+        let mut swash_cache = SwashCache::new();
+        let mut pixels = vec![0; 100 * 50];
+        let mut dt = render::DrawTarget::from_backing(100, 50, &mut pixels[..]);
+
+        let style = Style {
+            font_name: None,
+            font_size: 18.,
+            min_font_size: 8.,
+            overflow: TextOverflow::Wrap,
+            max_lines: None,
+        };
+        let text = Text::new("firefox".to_string(), style);
+        let position = layout::ScreenPosition { x: 0., y: 0. };
+        let mut render = |colour: &colour::Colour, width: f32| {
+            let space = layout::Space { width, height: 50. };
+            text.render_centred(
+                &mut dt,
+                &mut font_system,
+                &mut swash_cache,
+                1.,
+                colour,
+                &space,
+                &position,
+            );
+        };
+
+        render(&colour::Colour::BLACK, 100.);
+        render(&colour::Colour::WHITE, 100.);
+        render(&colour::Colour::BLACK, 100.);
+        assert_eq!(2, text.cache.borrow().images.len());
+
+        // a new size invalidates everything
+        render(&colour::Colour::BLACK, 80.);
+        assert_eq!(1, text.cache.borrow().images.len());
+
+        text.clear_cache();
+        assert!(text.cache.borrow().layout.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_overflow() {
+        #[derive(Deserialize)]
+        struct C {
+            text_overflow: TextOverflow,
+        }
+
+        let c: C = toml::from_str(r#"text_overflow = "shrink""#).unwrap();
+        assert_eq!(TextOverflow::Shrink, c.text_overflow);
+    }
+}
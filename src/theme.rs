@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+const THEMES_DIR: &str = "themes";
+
+// themes that are compiled into the binary.
+// a theme file in the user's config directory with the same name takes precedence.
+const BUILTIN_THEMES: &[(&str, &str)] = &[
+    (
+        "catppuccin-latte",
+        include_str!("themes/catppuccin-latte.toml"),
+    ),
+    (
+        "catppuccin-mocha",
+        include_str!("themes/catppuccin-mocha.toml"),
+    ),
+    ("gruvbox-dark", include_str!("themes/gruvbox-dark.toml")),
+    ("gruvbox-light", include_str!("themes/gruvbox-light.toml")),
+    ("nord", include_str!("themes/nord.toml")),
+    ("solarized-dark", include_str!("themes/solarized-dark.toml")),
+    (
+        "solarized-light",
+        include_str!("themes/solarized-light.toml"),
+    ),
+];
+
+fn theme_file(name: &str) -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix(crate::prog_name!())
+        .find_config_file(format!("{THEMES_DIR}/{name}.toml"))
+}
+
+fn builtin_theme(name: &str) -> Option<&'static str> {
+    BUILTIN_THEMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, contents)| *contents)
+}
+
+// theme names become file names, so they can't reach outside the themes directory.
+fn validate_name(name: &str) -> Result<()> {
+    anyhow::ensure!(
+        !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']),
+        "invalid theme name {name:?}"
+    );
+    Ok(())
+}
+
+// load the named theme as a table of config keys.
+pub fn load(name: &str) -> Result<toml::Table> {
+    validate_name(name)?;
+    let contents = match theme_file(name) {
+        Some(path) => std::fs::read_to_string(&path)
+            .with_context(|| format!("theme read at {}", path.display()))?,
+        None => match builtin_theme(name) {
+            Some(contents) => contents.to_string(),
+            None => anyhow::bail!(
+                "unknown theme {name:?}, available themes: {}",
+                available().join(", ")
+            ),
+        },
+    };
+
+    toml::from_str(&contents).with_context(|| format!("invalid theme {name:?}"))
+}
+
+// load one of the themes that are compiled in, ignoring the user's.
+#[cfg(test)]
+pub fn load_builtin(name: &str) -> Result<toml::Table> {
+    let contents = builtin_theme(name).with_context(|| format!("unknown theme {name:?}"))?;
+    toml::from_str(contents).with_context(|| format!("invalid theme {name:?}"))
+}
+
+// the names of every theme that can be loaded, sorted.
+pub fn available() -> Vec<String> {
+    let user_themes = xdg::BaseDirectories::with_prefix(crate::prog_name!())
+        .list_config_files(THEMES_DIR)
+        .into_iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+        .filter_map(|p| Some(p.file_stem()?.to_str()?.to_string()));
+
+    let mut names: Vec<String> = BUILTIN_THEMES
+        .iter()
+        .map(|(n, _)| n.to_string())
+        .chain(user_themes)
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_builtin_themes_are_valid() {
+        for (name, contents) in BUILTIN_THEMES {
+            let table: toml::Table = toml::from_str(contents).unwrap();
+            assert!(
+                table.try_into::<Config>().is_ok(),
+                "builtin theme {name} is invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        for name in ["", "../nord", "../../foo", "a/b", ".hidden", ".."] {
+            assert!(load(name).is_err(), "{name:?} should be rejected");
+        }
+        assert!(validate_name("gruvbox-dark").is_ok());
+    }
+
+    #[test]
+    fn test_builtin_themes_are_sorted() {
+        assert!(BUILTIN_THEMES.is_sorted_by_key(|(n, _)| n));
+    }
+}
@@ -0,0 +1,306 @@
+// a window switcher, listing the compositor's toplevel windows with
+// wlr-foreign-toplevel-management.
+//
+// this uses its own event queue, so that the windows can be listed before the
+// grid is built, and activated after our own surface has gone away.
+
+use crate::{drun, grid};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{wl_registry, wl_seat};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle, event_created_child};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+struct Toplevel {
+    handle: ZwlrForeignToplevelHandleV1,
+    title: String,
+    app_id: String,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct State {
+    toplevels: Vec<Toplevel>,
+}
+
+impl State {
+    fn toplevel(&mut self, handle: &ZwlrForeignToplevelHandleV1) -> Option<&mut Toplevel> {
+        self.toplevels.iter_mut().find(|t| &t.handle == handle)
+    }
+}
+
+pub struct Toplevels {
+    queue: EventQueue<State>,
+    state: State,
+    manager: ZwlrForeignToplevelManagerV1,
+    seat: wl_seat::WlSeat,
+}
+
+impl Toplevels {
+    // ask the compositor for its list of windows.
+    pub fn list(conn: &Connection) -> Result<Toplevels> {
+        let (globals, mut queue) = registry_queue_init::<State>(conn)?;
+        let qh = queue.handle();
+
+        let manager: ZwlrForeignToplevelManagerV1 = globals
+            .bind(&qh, 1..=3, ())
+            .context("the compositor doesn't support wlr-foreign-toplevel-management")?;
+        let seat: wl_seat::WlSeat = globals.bind(&qh, 1..=1, ()).context("no wl_seat")?;
+
+        // the first roundtrip delivers the toplevels.
+        // the second makes sure we've received the details of each of them.
+        let mut state = State::default();
+        queue.roundtrip(&mut state)?;
+        queue.roundtrip(&mut state)?;
+
+        state.toplevels.retain(|t| !t.closed);
+
+        Ok(Toplevels {
+            queue,
+            state,
+            manager,
+            seat,
+        })
+    }
+
+    // a choice for each window, whose value is its index.
+    pub fn choices(&self, data_dirs: &[PathBuf]) -> Vec<grid::Choice> {
+        self.state
+            .toplevels
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let display = if t.title.is_empty() {
+                    t.app_id.clone()
+                } else {
+                    t.title.clone()
+                };
+                let icon = (!t.app_id.is_empty()).then(|| {
+                    drun::find_icon(data_dirs, &t.app_id).unwrap_or_else(|| t.app_id.clone())
+                });
+
+                grid::Choice {
+                    display: Some(display),
+                    icon,
+                    ..grid::Choice::new(i.to_string())
+                }
+            })
+            .collect()
+    }
+
+    // focus the window with the given value from `choices`.
+    pub fn activate(mut self, value: &str) -> Result<()> {
+        let toplevel = value
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| self.state.toplevels.get(i))
+            .with_context(|| format!("no such window {value:?}"))?;
+
+        toplevel.handle.activate(&self.seat);
+        self.manager.stop();
+        self.queue.roundtrip(&mut self.state)?;
+
+        Ok(())
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_seat::WlSeat,
+        _: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.push(Toplevel {
+                handle: toplevel,
+                title: String::new(),
+                app_id: String::new(),
+                closed: false,
+            });
+        }
+    }
+
+    event_created_child!(State, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(toplevel) = state.toplevel(handle) else {
+            log::warn!("event for unknown toplevel {}", handle.id());
+            return;
+        };
+
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => toplevel.title = title,
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.app_id = app_id,
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => toplevel.closed = true,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_compositor::TestCompositor;
+    use wayland_protocols_wlr::foreign_toplevel::v1::server::{
+        zwlr_foreign_toplevel_handle_v1 as server_handle,
+        zwlr_foreign_toplevel_manager_v1 as server_manager,
+    };
+    use wayland_server::protocol::wl_seat as server_seat;
+    use wayland_server::{
+        Client, DataInit, Dispatch as ServerDispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    };
+
+    struct Compositor {
+        windows: Vec<(&'static str, &'static str)>,
+        activated: Option<usize>,
+    }
+
+    impl GlobalDispatch<server_manager::ZwlrForeignToplevelManagerV1, ()> for Compositor {
+        fn bind(
+            state: &mut Self,
+            handle: &DisplayHandle,
+            client: &Client,
+            resource: New<server_manager::ZwlrForeignToplevelManagerV1>,
+            _: &(),
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            let manager = data_init.init(resource, ());
+            for (i, (title, app_id)) in state.windows.iter().enumerate() {
+                let toplevel = client
+                    .create_resource::<server_handle::ZwlrForeignToplevelHandleV1, _, Self>(
+                        handle,
+                        manager.version(),
+                        i,
+                    )
+                    .unwrap();
+                manager.toplevel(&toplevel);
+                toplevel.title(title.to_string());
+                toplevel.app_id(app_id.to_string());
+                toplevel.done();
+            }
+        }
+    }
+
+    impl ServerDispatch<server_manager::ZwlrForeignToplevelManagerV1, ()> for Compositor {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            manager: &server_manager::ZwlrForeignToplevelManagerV1,
+            request: server_manager::Request,
+            _: &(),
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+            if let server_manager::Request::Stop = request {
+                manager.finished();
+            }
+        }
+    }
+
+    impl ServerDispatch<server_handle::ZwlrForeignToplevelHandleV1, usize> for Compositor {
+        fn request(
+            state: &mut Self,
+            _: &Client,
+            _: &server_handle::ZwlrForeignToplevelHandleV1,
+            request: server_handle::Request,
+            index: &usize,
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+            if let server_handle::Request::Activate { .. } = request {
+                state.activated = Some(*index);
+            }
+        }
+    }
+
+    impl GlobalDispatch<server_seat::WlSeat, ()> for Compositor {
+        fn bind(
+            _: &mut Self,
+            _: &DisplayHandle,
+            _: &Client,
+            resource: New<server_seat::WlSeat>,
+            _: &(),
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            data_init.init(resource, ());
+        }
+    }
+
+    impl ServerDispatch<server_seat::WlSeat, ()> for Compositor {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            _: &server_seat::WlSeat,
+            _: server_seat::Request,
+            _: &(),
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+        }
+    }
+
+    #[test]
+    fn test_list_and_activate() {
+        let compositor = Compositor {
+            windows: vec![("Mozilla Firefox", "firefox"), ("", "kitty")],
+            activated: None,
+        };
+        let (conn, server) = TestCompositor::spawn(compositor, |dh| {
+            dh.create_global::<Compositor, server_manager::ZwlrForeignToplevelManagerV1, ()>(3, ());
+            dh.create_global::<Compositor, server_seat::WlSeat, ()>(1, ());
+        });
+
+        let toplevels = Toplevels::list(&conn).unwrap();
+        let choices = toplevels.choices(&[]);
+
+        let displays: Vec<_> = choices.iter().map(|c| c.display.as_deref()).collect();
+        assert_eq!(vec![Some("Mozilla Firefox"), Some("kitty")], displays);
+        assert_eq!(Some("firefox"), choices[0].icon.as_deref());
+
+        toplevels.activate(&choices[1].value).unwrap();
+
+        assert_eq!(Some(1), server.stop().activated);
+    }
+}
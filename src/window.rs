@@ -1,10 +1,19 @@
+use crate::accessibility;
+use crate::animation;
 use crate::config;
+use crate::fonts;
 use crate::grid;
+use crate::icon;
+use crate::input;
 use crate::layout;
 use crate::render;
 use crate::state;
 
-use smithay_client_toolkit::reexports::calloop::EventLoop;
+use anyhow::Context as _;
+use smithay_client_toolkit::reexports::calloop::timer::{TimeoutAction, Timer};
+use smithay_client_toolkit::reexports::calloop::{
+    Dispatcher, EventLoop, LoopHandle, RegistrationToken, channel,
+};
 use smithay_client_toolkit::reexports::calloop_wayland_source::WaylandSource;
 use smithay_client_toolkit::{
     compositor::CompositorState,
@@ -22,8 +31,10 @@ use smithay_client_toolkit::{
     shell::{WaylandSurface, wlr_layer::LayerSurface},
     shm::{Shm, slot::Buffer, slot::SlotPool},
 };
+use smithay_client_toolkit::reexports::protocols::wp::text_input::zv3::client::zwp_text_input_manager_v3::ZwpTextInputManagerV3;
+use std::time::Instant;
 use wayland_client::protocol::wl_keyboard;
-use wayland_client::{Connection, globals::registry_queue_init};
+use wayland_client::{Connection, QueueHandle, globals::registry_queue_init};
 
 mod compositor;
 mod keyboard;
@@ -31,6 +42,7 @@ mod layer_shell;
 mod output;
 mod seat;
 mod shm;
+mod text_input;
 
 const DEFAULT_SCALE: u16 = 1;
 
@@ -39,23 +51,147 @@ pub struct Window {
     state: state::State,
     drawable_items: render::DrawableItems,
 
-    registry_state: RegistryState,
-    seat_state: SeatState,
-    output_state: OutputState,
-
     layer: LayerSurface,
-    keyboard: Option<wl_keyboard::WlKeyboard>,
 
     buffer: Option<Buffer>,
     pool: SlotPool,
-    shm: Shm,
 
     width: u32,
     height: u32,
     scale: u16,
 
+    // have we received the first configure? we can't draw before then.
+    configured: bool,
+    // have we asked for a new size? we'll draw once it's been configured.
+    resizing: bool,
+    // are more items still being read?
+    loading: bool,
+    // the submenu command that's running, if there is one
+    submenu_command: Option<RegistrationToken>,
+    // how the output of submenu commands is read
+    format: input::Format,
+
+    animations: animation::Animations,
+    // have we asked the compositor to tell us when to draw the next frame?
+    frame_requested: bool,
+
+    qh: QueueHandle<Window>,
+    loop_handle: LoopHandle<'static, Window>,
+    // the context's event queue and screen reader requests, in this window's event loop
+    wayland_token: RegistrationToken,
+    accessibility_token: RegistrationToken,
+
+    context: Context,
+}
+
+// the parts of a window that are expensive to set up, and can be reused by the
+// next window (see the daemon).
+pub struct Context {
+    conn: Connection,
+    // the event queue is lent to each window's event loop in turn
+    wayland: Dispatcher<'static, WaylandSource<Window>, Window>,
+    qh: QueueHandle<Window>,
+
+    // the globals that we've bound, and the seats and outputs that we know
+    // about. the compositor keeps everything that we bind until we disconnect,
+    // so these are kept for the next window rather than bound again.
+    registry_state: RegistryState,
+    seat_state: SeatState,
+    output_state: OutputState,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+    shm: Shm,
+
+    // for screen readers, which see each window in turn as the same one.
+    // their requests are lent to each window's event loop, like the event queue.
+    accessibility: accesskit_unix::Adapter,
+    accessibility_requests: Dispatcher<'static, channel::Channel<accessibility::Request>, Window>,
+
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    // for typing with an input method, if the compositor supports one
+    text_input_manager: Option<ZwpTextInputManagerV3>,
+    text_input: Option<text_input::TextInput>,
+
     font_system: cosmic_text::FontSystem,
     swash_cache: cosmic_text::SwashCache,
+
+    icon_cache: icon::Cache,
+}
+
+impl Context {
+    // `config` decides which fonts are loaded.
+    pub fn new(config: &config::Config) -> anyhow::Result<Context> {
+        // All Wayland apps start by connecting the compositor (server).
+        Self::with_connection(Connection::connect_to_env()?, config)
+    }
+
+    pub(crate) fn with_connection(
+        conn: Connection,
+        config: &config::Config,
+    ) -> anyhow::Result<Context> {
+        // Enumerate the list of globals to get the protocols the server implements.
+        let (globals, event_queue) = registry_queue_init(&conn)?;
+        let qh = event_queue.handle();
+        let wayland = Dispatcher::new(
+            WaylandSource::new(conn.clone(), event_queue),
+            |_, queue, window| queue.dispatch_pending(window),
+        );
+
+        // The compositor (not to be confused with the server which is commonly called the compositor) allows
+        // configuring surfaces to be presented.
+        let compositor =
+            CompositorState::bind(&globals, &qh).context("wl_compositor is not available")?;
+        // This app uses the wlr layer shell, which may not be available with every compositor.
+        let layer_shell =
+            LayerShell::bind(&globals, &qh).context("layer shell is not available")?;
+        // Since we are not using the GPU in this example, we use wl_shm to allow software rendering to a buffer
+        // we share with the compositor process.
+        let shm = Shm::bind(&globals, &qh).context("wl_shm is not available")?;
+        // input methods are optional. we get a text input for the seat along
+        // with its keyboard (see seat.rs).
+        let text_input_manager = globals.bind(&qh, 1..=1, ()).ok();
+        if text_input_manager.is_none() {
+            log::debug!(
+                "the compositor doesn't support text-input-v3, so input methods won't work"
+            );
+        }
+
+        let (accessibility, accessibility_requests) = accessibility::adapter();
+        let accessibility_requests =
+            Dispatcher::new(accessibility_requests, |event, _, window: &mut Window| {
+                if let channel::Event::Msg(request) = event {
+                    window.accessibility_request(request);
+                }
+            });
+
+        Ok(Context {
+            conn,
+            wayland,
+
+            // Seats and outputs may be hotplugged at runtime, therefore we need to setup a registry state to
+            // listen for seats and outputs.
+            registry_state: RegistryState::new(&globals),
+            seat_state: SeatState::new(&globals, &qh),
+            output_state: OutputState::new(&globals, &qh),
+            compositor,
+            layer_shell,
+            shm,
+            qh,
+
+            accessibility,
+            accessibility_requests,
+
+            keyboard: None,
+            text_input_manager,
+            text_input: None,
+
+            // cosmic text
+            font_system: fonts::font_system(config),
+            swash_cache: cosmic_text::SwashCache::new(),
+
+            icon_cache: icon::Cache::default(),
+        })
+    }
 }
 
 impl Window {
@@ -68,38 +204,29 @@ impl Window {
     }
 
     pub fn new(
+        context: Context,
         config: config::Config,
-        options: &[(String, Option<String>)],
-    ) -> anyhow::Result<(Self, EventLoop<'_, Window>)> {
-        // All Wayland apps start by connecting the compositor (server).
-        let conn = Connection::connect_to_env().unwrap();
-
-        // Enumerate the list of globals to get the protocols the server implements.
-        let (globals, event_queue) = registry_queue_init(&conn)?;
-        let qh = event_queue.handle();
+        grid: grid::Grid,
+        initial_value: Option<&str>,
+    ) -> (Self, EventLoop<'static, Window>) {
+        let qh = context.qh.clone();
 
         let event_loop: EventLoop<Window> =
             EventLoop::try_new().expect("Failed to initialize the event loop!");
-        let loop_handle = event_loop.handle();
-        WaylandSource::new(conn.clone(), event_queue)
-            .insert(loop_handle)
-            .unwrap();
-
-        // The compositor (not to be confused with the server which is commonly called the compositor) allows
-        // configuring surfaces to be presented.
-        let compositor =
-            CompositorState::bind(&globals, &qh).expect("wl_compositor is not available");
-        // This app uses the wlr layer shell, which may not be available with every compositor.
-        let layer_shell = LayerShell::bind(&globals, &qh).expect("layer shell is not available");
-        // Since we are not using the GPU in this example, we use wl_shm to allow software rendering to a buffer
-        // we share with the compositor process.
-        let shm = Shm::bind(&globals, &qh).expect("wl_shm is not available");
+        let wayland_token = event_loop
+            .handle()
+            .register_dispatcher(context.wayland.clone())
+            .expect("failed to listen to the compositor");
+        let accessibility_token = event_loop
+            .handle()
+            .register_dispatcher(context.accessibility_requests.clone())
+            .expect("failed to listen for screen readers");
 
         // A layer surface is created from a surface.
-        let surface = compositor.create_surface(&qh);
+        let surface = context.compositor.create_surface(&qh);
 
         // And then we create the layer shell.
-        let layer = layer_shell.create_layer_surface(
+        let layer = context.layer_shell.create_layer_surface(
             &qh,
             surface,
             Layer::Top,
@@ -107,16 +234,14 @@ impl Window {
             None,
         );
 
-        let grid = grid::Grid::new(options)?;
-
         // The logical dimensions of our layer.
-        let width: u32 = grid.width as u32
-            * (config.item_width + config.item_margin + 2. * config.border_width) as u32;
-        let height: u32 = grid.height as u32
-            * (config.item_height + config.item_margin + 2. * config.border_width) as u32;
+        let (width, height) = render::layer_size(&config, &grid);
 
         // Configure the layer surface, providing things like the anchor on screen, desired size and the keyboard
         // interactivity
+        //
+        // the input method follows the keyboard focus, so taking it exclusively
+        // also means that the text input is enabled for us as soon as we're mapped.
         layer.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
         layer.set_size(width, height);
 
@@ -130,42 +255,57 @@ impl Window {
         // Initially we don't know the real scale. The compositor will tell us later
         let scale = DEFAULT_SCALE;
         let buffer_len = width * scale as u32 * height * scale as u32 * 4;
-        let pool = SlotPool::new(buffer_len as usize, &shm).expect("Failed to create pool");
+        let pool = SlotPool::new(buffer_len as usize, &context.shm).expect("Failed to create pool");
 
         let drawable_items = render::DrawableItems::from_grid(&config, &grid);
+        let animations = animation::Animations::new(config.animation_duration_ms);
+
+        let mut state = state::State::new(grid, initial_value);
+        state.wrap = config.wrap_cursor;
 
         let window = Window {
             config,
-            state: state::State::new(grid),
+            state,
             drawable_items,
 
-            // Seats and outputs may be hotplugged at runtime, therefore we need to setup a registry state to
-            // listen for seats and outputs.
-            registry_state: RegistryState::new(&globals),
-            seat_state: SeatState::new(&globals, &qh),
-            output_state: OutputState::new(&globals, &qh),
-
             buffer: None,
             pool,
-            shm,
 
             scale,
             width,
             height,
+            configured: false,
+            resizing: false,
+            loading: false,
+            submenu_command: None,
+            format: input::Format::default(),
+            animations,
+            frame_requested: false,
             layer,
-            keyboard: None,
 
-            // cosmic text
-            font_system: cosmic_text::FontSystem::new(),
-            swash_cache: cosmic_text::SwashCache::new(),
+            qh,
+            loop_handle: event_loop.handle(),
+            wayland_token,
+            accessibility_token,
+
+            context,
         };
 
-        Ok((window, event_loop))
+        (window, event_loop)
     }
 
     // much of this implementation is borrowed from yofi under the MIT license
     // copyright 2018 kitsu
     pub fn draw(&mut self) {
+        if self.resizing {
+            // anything drawn now would be the wrong size for the grid
+            return;
+        }
+
+        if self.state.needs_redraw || self.state.cursor_needs_rerender() {
+            self.update_accessibility();
+        }
+
         let width = self.width().try_into().expect("width overflow");
         let height = self.height().try_into().expect("height overflow");
         let stride = width * 4;
@@ -224,13 +364,41 @@ impl Window {
                 height: self.height as f32,
             },
             config: &self.config,
-            font_system: &mut self.font_system,
-            swash_cache: &mut self.swash_cache,
+            font_system: &mut self.context.font_system,
+            swash_cache: &mut self.context.swash_cache,
+            icon_cache: &mut self.context.icon_cache,
             scale: self.scale as f32,
         };
 
+        let surface = self.layer.wl_surface();
+        let now = Instant::now();
+
         if self.state.needs_redraw {
-            // something fundamental changed (e.g. scale factor)
+            // the grid has changed underneath any slide
+            self.animations.slide = None;
+        } else if self.state.cursor_needs_rerender()
+            && self.animations.opening.is_none()
+            && let Some(previous) = self.animations.start_slide(
+                self.state.rendered_cursor_position.clone(),
+                self.state.cursor_position.clone(),
+                now,
+            )
+        {
+            // the cursor moved again before the last slide finished.
+            // skip to its end, so that nothing is left behind in the area it crossed.
+            let (pos, space) = render::cursor_slide(
+                &mut dt,
+                &self.drawable_items,
+                &previous.from,
+                &previous.to,
+                1.,
+                &mut draw_args,
+            );
+            damage(surface, &pos, &space);
+        }
+
+        if self.state.needs_redraw || self.animations.opening.is_some() {
+            // something fundamental changed (e.g. scale factor), or we're still opening.
             // we'll redraw the full screen.
             dt.clear(raqote::SolidSource {
                 r: 0,
@@ -239,6 +407,14 @@ impl Window {
                 a: 0,
             });
 
+            let opening = self
+                .animations
+                .opening
+                .map(|a| animation::ease_out(a.progress(now)));
+            if let Some(t) = opening {
+                dt.set_transform(&render::opening_transform(t, &draw_args));
+            }
+
             render::grid(
                 &mut dt,
                 &self.drawable_items,
@@ -246,37 +422,78 @@ impl Window {
                 &mut draw_args,
             );
 
+            if self.loading || self.submenu_command.is_some() {
+                render::loading_indicator(&mut dt, &mut draw_args);
+            }
+
+            if let Some(t) = opening {
+                dt.set_transform(&raqote::Transform::identity());
+                render::fade(&mut dt, t);
+            }
+
             // Damage the entire window
-            self.layer.wl_surface().damage_buffer(0, 0, width, height);
+            surface.damage_buffer(0, 0, width, height);
 
             self.state.needs_redraw = false;
+        } else if let Some(slide) = &self.animations.slide {
+            // the cursor is moving.
+            // we only need to redraw the area that the highlight crosses.
+            let (pos, space) = render::cursor_slide(
+                &mut dt,
+                &self.drawable_items,
+                &slide.from,
+                &slide.to,
+                animation::ease_out(slide.animation.progress(now)),
+                &mut draw_args,
+            );
+            damage(surface, &pos, &space);
         } else if self.state.cursor_needs_rerender() {
             // the cursor moved
             // we only need to redraw the old & new cursor positions
-            // TODO: are these clones necessary?
-            let cursor_position = self.state.cursor_position.clone();
-            let rendered_position = self.state.rendered_cursor_position.clone();
-            let items_to_redraw = self
-                .drawable_items
-                .at_positions(&[cursor_position, rendered_position]);
+            let items_to_redraw = [
+                &self.state.cursor_position,
+                &self.state.rendered_cursor_position,
+            ]
+            .into_iter()
+            .filter_map(|position| self.drawable_items.at(position));
             for item in items_to_redraw {
-                let (item_pos, item_space) = render::draw_grid_item(
+                let (pos, space) = render::draw_grid_item(
                     &mut dt,
                     item,
                     &self.state.cursor_position,
                     &mut draw_args,
                 );
-
-                // Damage just the area we drew
-                self.layer.wl_surface().damage_buffer(
-                    item_pos.x as i32,
-                    item_pos.y as i32,
-                    item_space.width as i32,
-                    item_space.height as i32,
-                );
+                damage(surface, &pos, &space);
             }
         }
 
+        if let Some(pulse) = self.animations.pulse
+            && let Some(item) = self.drawable_items.at(&self.state.cursor_position)
+        {
+            let amount = (pulse.progress(now) * std::f32::consts::PI).sin();
+            let (pos, space) = render::selection_pulse(&mut dt, item, amount, &mut draw_args);
+            damage(surface, &pos, &space);
+        }
+
+        // each animation's last frame has now been drawn
+        self.animations.remove_finished(now);
+        if self.animations.running() && !self.frame_requested {
+            surface.frame(&self.qh, surface.clone());
+            self.frame_requested = true;
+        }
+
+        // the query goes over the top of the grid, so it's drawn last
+        let preedit = self
+            .context
+            .text_input
+            .as_ref()
+            .map_or("", |t| t.preedit.as_str());
+        if !self.state.query.is_empty() || !preedit.is_empty() {
+            let (pos, space) =
+                render::query_strip(&mut dt, &self.state.query, preedit, &mut draw_args);
+            damage(surface, &pos, &space);
+        }
+
         self.state.rendered_cursor_position = self.state.cursor_position.clone();
 
         // Tell the compositor that we're done.
@@ -288,11 +505,262 @@ impl Window {
         self.buffer = Some(buffer);
     }
 
+    // replace the items in the grid, e.g. as more arrive on stdin.
+    pub fn set_choices(&mut self, choices: &[grid::Choice]) -> anyhow::Result<()> {
+        let grid = grid::Grid::new(choices)?;
+        let in_submenu = self.state.in_submenu();
+        self.state.set_grid(grid);
+
+        // a submenu's parent isn't shown, so there's nothing to redraw
+        if !in_submenu {
+            self.grid_changed();
+        }
+
+        Ok(())
+    }
+
+    // the grid that's shown has been replaced, so redraw it from scratch.
+    fn grid_changed(&mut self) {
+        let (width, height) = render::layer_size(&self.config, &self.state.grid);
+        self.drawable_items.update(&self.config, &self.state.grid);
+
+        if (width, height) != (self.width, self.height) {
+            // we'll redraw when the compositor configures the new size.
+            self.layer.set_size(width, height);
+            self.layer.commit();
+            self.resizing = true;
+        } else if self.configured {
+            self.draw();
+        }
+    }
+
+    // how to read the choices that submenu commands print.
+    pub fn set_format(&mut self, format: input::Format) {
+        self.format = format;
+    }
+
+    // show the submenu under the cursor in place of the grid.
+    fn enter_submenu(&mut self, submenu: grid::Submenu) {
+        self.cancel_submenu_command();
+        match submenu {
+            grid::Submenu::Children(children) => self.show_submenu(Ok(children)),
+            grid::Submenu::Command(command) => self.run_submenu_command(&command),
+        }
+    }
+
+    // the submenu is shown once its command has finished. until then, the
+    // loading indicator is shown and the grid can still be used.
+    fn run_submenu_command(&mut self, command: &str) {
+        let choices = input::read_command(command, &self.format);
+        let inserted = self.loop_handle.insert_source(choices, |event, _, window| {
+            if let channel::Event::Msg(choices) = event {
+                window.submenu_command = None;
+                window.show_submenu(choices);
+            }
+        });
+
+        match inserted {
+            Ok(token) => {
+                self.submenu_command = Some(token);
+                self.redraw();
+            }
+            Err(e) => log::error!("can't open the submenu: {e}"),
+        }
+    }
+
+    // forget about a submenu command that's still running, e.g. because
+    // something else has been chosen since. returns true if there was one.
+    fn cancel_submenu_command(&mut self) -> bool {
+        let Some(token) = self.submenu_command.take() else {
+            return false;
+        };
+        self.loop_handle.remove(token);
+        self.state.needs_redraw = true;
+        true
+    }
+
+    fn show_submenu(&mut self, choices: anyhow::Result<Vec<grid::Choice>>) {
+        // like the top level, only the first choices that fit are shown
+        let choices = choices.map(|mut c| {
+            c.truncate(grid::max_items());
+            c
+        });
+
+        match choices.and_then(|c| grid::Grid::new(&c)) {
+            Ok(grid) => {
+                self.state.enter(grid);
+                self.grid_changed();
+            }
+            // stay where we are, so that something else can be chosen
+            Err(e) => {
+                log::error!("can't open the submenu: {e:#}");
+                // hide the loading indicator
+                self.redraw();
+            }
+        }
+    }
+
+    // go back from a submenu to the menu that it was opened from.
+    // if a submenu's command is still running, it's cancelled instead.
+    pub fn leave_submenu(&mut self) {
+        if self.cancel_submenu_command() {
+            self.redraw();
+        } else if self.state.leave() {
+            self.grid_changed();
+        }
+    }
+
+    // move the cursor to the item with this value, now or once it's been added.
+    pub fn set_initial_value(&mut self, value: String) {
+        self.state.set_initial_value(value);
+        if self.configured && self.state.cursor_needs_rerender() {
+            self.draw();
+        }
+    }
+
+    // show or hide the loading indicator.
+    pub fn set_loading(&mut self, loading: bool) {
+        if self.loading != loading {
+            self.loading = loading;
+            self.redraw();
+        }
+    }
+
+    // draw again, e.g. once the loading indicator has changed.
+    fn redraw(&mut self) {
+        self.state.needs_redraw = true;
+        if self.configured {
+            self.draw();
+        }
+    }
+
+    // close the window and its event loop, keeping what can be reused for the
+    // next one.
+    pub fn into_context(self, event_loop: EventLoop<'static, Window>) -> Context {
+        // the event queue and screen reader requests go back to the context
+        event_loop.handle().remove(self.wayland_token);
+        event_loop.handle().remove(self.accessibility_token);
+        drop(event_loop);
+
+        // everything else (the surface, buffers, etc.) is destroyed along with the window.
+        let Window { mut context, .. } = self;
+        context.accessibility.update_window_focus_state(false);
+
+        // make sure that the compositor hears that the surface has gone
+        // before we wait for the next request.
+        if let Err(e) = context.conn.flush() {
+            log::warn!("failed to flush the wayland connection: {e}");
+        }
+
+        context
+    }
+
+    pub fn exit(&mut self) {
+        self.state.should_exit = true;
+        self.animations.pulse = None;
+    }
+
+    // choose the item under the cursor, and exit once it's finished pulsing.
+    // if it's a submenu, show that instead.
+    pub fn accept(&mut self) {
+        if let Some(submenu) = self.state.selected_submenu() {
+            self.enter_submenu(submenu.clone());
+            return;
+        }
+
+        self.state.select();
+        if !self.state.should_exit || !self.configured {
+            return;
+        }
+
+        self.animations.start_pulse(Instant::now());
+        if self.animations.pulse.is_some() {
+            // frames stop arriving if we're hidden, so we can't rely on them to
+            // finish the animation.
+            let timer = Timer::from_duration(self.animations.duration());
+            let inserted = self.loop_handle.insert_source(timer, |_, _, window| {
+                window.animations.pulse = None;
+                TimeoutAction::Drop
+            });
+            if let Err(e) = inserted {
+                log::warn!("failed to time the selection animation: {e}");
+                self.animations.pulse = None;
+            }
+        }
+    }
+
+    // stay open after an item has been chosen, e.g. to show the items that a
+    // script printed in response.
+    pub fn resume(&mut self) {
+        let in_submenu = self.state.in_submenu();
+        self.state.resume();
+        if in_submenu {
+            self.grid_changed();
+        }
+    }
+
+    // tell any screen reader about the grid and the cursor.
+    fn update_accessibility(&mut self) {
+        self.context
+            .accessibility
+            .update_if_active(|| accessibility::tree(&self.state));
+    }
+
+    fn accessibility_request(&mut self, request: accessibility::Request) {
+        let request = match request {
+            accessibility::Request::Activate => {
+                self.update_accessibility();
+                return;
+            }
+            accessibility::Request::Action(request) => request,
+        };
+        let Some(index) = accessibility::item_index(request.target_node) else {
+            return;
+        };
+        if self.state.should_exit {
+            return;
+        }
+
+        self.state.cursor_move_to_index(index);
+        if let accesskit::Action::Click = request.action {
+            self.accept();
+        }
+        if self.configured {
+            self.draw();
+        }
+    }
+
     pub fn should_exit(&self) -> bool {
-        self.state.should_exit
+        self.state.should_exit && self.animations.pulse.is_none()
+    }
+
+    // the value that the user chose, once we've exited.
+    pub fn selected_value(&self) -> Option<&str> {
+        self.selected().map(|s| s.value.as_str())
+    }
+
+    // the item that the user chose, once we've exited.
+    pub fn selected(&self) -> Option<&state::Selection> {
+        self.state.selected.as_ref()
     }
 }
 
+// tell the compositor that we've drawn to an area, rounded out to whole pixels.
+fn damage(
+    surface: &protocol::wl_surface::WlSurface,
+    pos: &layout::ScreenPosition,
+    space: &layout::Space,
+) {
+    let x = pos.x.floor();
+    let y = pos.y.floor();
+    surface.damage_buffer(
+        x as i32,
+        y as i32,
+        (pos.x + space.width - x).ceil() as i32,
+        (pos.y + space.height - y).ceil() as i32,
+    );
+}
+
 delegate_compositor!(Window);
 delegate_output!(Window);
 delegate_shm!(Window);
@@ -306,7 +774,72 @@ delegate_registry!(Window);
 
 impl ProvidesRegistryState for Window {
     fn registry(&mut self) -> &mut RegistryState {
-        &mut self.registry_state
+        &mut self.context.registry_state
     }
     registry_handlers![OutputState, SeatState];
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_compositor::Shell;
+    use accesskit::{Action, ActionRequest, TreeId};
+
+    fn request(action: Action, index: usize) -> accessibility::Request {
+        accessibility::Request::Action(ActionRequest {
+            action,
+            target_tree: TreeId::ROOT,
+            target_node: accessibility::item_id(index),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn test_accessibility_requests() {
+        let (conn, server) = Shell::spawn();
+        let font =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fonts/Tuffy.ttf");
+        let config = || config::Config {
+            fonts: vec![font.clone()],
+            ..config::Config::default()
+        };
+        let context = Context::with_connection(conn, &config()).unwrap();
+        let choices = ["a", "b", "c"].map(|v| grid::Choice::new(v.to_string()));
+        let grid = || grid::Grid::new(&choices).unwrap();
+        let (mut window, event_loop) = Window::new(context, config(), grid(), None);
+
+        // a screen reader moves the focus, which moves the cursor
+        window.accessibility_request(request(Action::Focus, 2));
+        let items = &window.state.grid.items;
+        assert_eq!(items.position_of(2).unwrap(), window.state.cursor_position);
+        assert_eq!(
+            accessibility::item_id(2),
+            accessibility::tree(&window.state).focus
+        );
+        // the screen reader is sent the tree when this is drawn
+        assert!(window.state.cursor_needs_rerender());
+
+        // the focus follows the cursor when it's moved from the keyboard too
+        window.state.cursor_move_next();
+        assert_eq!(
+            accessibility::item_id(0),
+            accessibility::tree(&window.state).focus
+        );
+
+        // clicking an item chooses it
+        window.accessibility_request(request(Action::Click, 1));
+        assert_eq!(Some("b"), window.selected_value());
+
+        // the adapter is kept for the next window, which takes its requests
+        let context = window.into_context(event_loop);
+        let (mut window, event_loop) = Window::new(context, config(), grid(), None);
+        window.accessibility_request(request(Action::Focus, 1));
+        assert_eq!(
+            accessibility::item_id(1),
+            accessibility::tree(&window.state).focus
+        );
+
+        window.into_context(event_loop);
+        server.stop();
+    }
+}
@@ -20,7 +20,11 @@ impl CompositorHandler for Window {
 
             self.pool
                 .resize(size)
-                .expect(format!("pool resize to {size} failed").as_str());
+                .unwrap_or_else(|_| panic!("pool resize to {size} failed"));
+
+            // icons and text were rasterised for the old scale.
+            self.context.icon_cache.clear();
+            self.drawable_items.clear_caches();
 
             // we need to clear the window and redraw everything.
             self.state.needs_redraw = true;
@@ -45,6 +49,7 @@ impl CompositorHandler for Window {
         _surface: &wl_surface::WlSurface,
         _time: u32,
     ) {
+        self.frame_requested = false;
         self.draw();
     }
 
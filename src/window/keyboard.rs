@@ -19,6 +19,7 @@ impl KeyboardHandler for Window {
         _: &[u32],
         _keysyms: &[Keysym],
     ) {
+        self.context.accessibility.update_window_focus_state(true);
     }
 
     fn leave(
@@ -29,6 +30,7 @@ impl KeyboardHandler for Window {
         _surface: &wl_surface::WlSurface,
         _: u32,
     ) {
+        self.context.accessibility.update_window_focus_state(false);
     }
 
     fn press_key(
@@ -39,13 +41,29 @@ impl KeyboardHandler for Window {
         _: u32,
         event: KeyEvent,
     ) {
+        if self.state.should_exit {
+            // an item has been chosen, and we're waiting for its animation to finish
+            return;
+        }
+
         match event.keysym {
+            // the first escape forgets what's been typed
             Keysym::Escape => {
-                self.state.should_exit = true;
+                if self.state.query.is_empty() {
+                    self.state.should_exit = true;
+                } else {
+                    self.state.clear_query();
+                }
             }
             Keysym::Return => {
-                println!("{}", self.state.get_selected_value());
-                self.state.should_exit = true;
+                self.accept();
+            }
+            Keysym::BackSpace => {
+                if self.state.query.is_empty() {
+                    self.leave_submenu();
+                } else {
+                    self.state.erase_query_char();
+                }
             }
             Keysym::h | Keysym::Left => {
                 self.state.cursor_move_left();
@@ -59,6 +77,41 @@ impl KeyboardHandler for Window {
             Keysym::s | Keysym::Right => {
                 self.state.cursor_move_right();
             }
+            // the corners of the numpad, with or without num lock
+            Keysym::KP_7 | Keysym::KP_Home => {
+                self.state.cursor_move_up_left();
+            }
+            Keysym::KP_9 | Keysym::KP_Page_Up => {
+                self.state.cursor_move_up_right();
+            }
+            Keysym::KP_1 | Keysym::KP_End => {
+                self.state.cursor_move_down_left();
+            }
+            Keysym::KP_3 | Keysym::KP_Page_Down => {
+                self.state.cursor_move_down_right();
+            }
+            Keysym::Home => {
+                self.state.cursor_move_home();
+            }
+            Keysym::Tab => {
+                self.state.cursor_move_next();
+            }
+            // shift-tab
+            Keysym::ISO_Left_Tab => {
+                self.state.cursor_move_previous();
+            }
+            Keysym::Page_Down => {
+                self.state.cursor_move_outwards();
+            }
+            Keysym::Page_Up => {
+                self.state.cursor_move_inwards();
+            }
+            Keysym::bracketright => {
+                self.state.cursor_move_next_section();
+            }
+            Keysym::bracketleft => {
+                self.state.cursor_move_previous_section();
+            }
             _ => {}
         }
 
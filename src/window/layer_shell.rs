@@ -8,7 +8,7 @@ use smithay_client_toolkit::shell::wlr_layer::{
 
 impl LayerShellHandler for Window {
     fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
-        self.state.should_exit = true;
+        self.exit();
     }
 
     fn configure(
@@ -21,9 +21,25 @@ impl LayerShellHandler for Window {
     ) {
         let (w, h) = configure.new_size;
 
+        let resized = (w, h) != (self.width, self.height);
+        if resized {
+            self.state.needs_redraw = true;
+        }
+
         self.width = w;
         self.height = h;
 
+        if resized {
+            // the query strip has moved
+            self.update_text_input();
+        }
+
+        if !self.configured {
+            self.animations.start_opening(std::time::Instant::now());
+        }
+        self.configured = true;
+        self.resizing = false;
+
         self.draw();
     }
 }
@@ -7,7 +7,7 @@ use smithay_client_toolkit::{
 
 impl OutputHandler for Window {
     fn output_state(&mut self) -> &mut OutputState {
-        &mut self.output_state
+        &mut self.context.output_state
     }
 
     fn new_output(
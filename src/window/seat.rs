@@ -7,7 +7,7 @@ use smithay_client_toolkit::{
 
 impl SeatHandler for Window {
     fn seat_state(&mut self) -> &mut SeatState {
-        &mut self.seat_state
+        &mut self.context.seat_state
     }
 
     fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
@@ -19,12 +19,20 @@ impl SeatHandler for Window {
         seat: wl_seat::WlSeat,
         capability: Capability,
     ) {
-        if capability == Capability::Keyboard && self.keyboard.is_none() {
+        if capability == Capability::Keyboard && self.context.keyboard.is_none() {
             let keyboard = self
+                .context
                 .seat_state
                 .get_keyboard(qh, &seat, None)
                 .expect("Failed to create keyboard");
-            self.keyboard = Some(keyboard);
+            self.context.keyboard = Some(keyboard);
+
+            if let Some(manager) = &self.context.text_input_manager
+                && self.context.text_input.is_none()
+            {
+                let input = manager.get_text_input(&seat, qh, ());
+                self.context.text_input = Some(super::text_input::TextInput::new(input));
+            }
         }
     }
 
@@ -35,8 +43,11 @@ impl SeatHandler for Window {
         _: wl_seat::WlSeat,
         capability: Capability,
     ) {
-        if capability == Capability::Keyboard && self.keyboard.is_some() {
-            self.keyboard.take().unwrap().release();
+        if capability == Capability::Keyboard && self.context.keyboard.is_some() {
+            self.context.keyboard.take().unwrap().release();
+            if let Some(text_input) = self.context.text_input.take() {
+                text_input.destroy();
+            }
         }
     }
 
@@ -0,0 +1,155 @@
+// typing to find an item with an input method, using text-input-v3.
+//
+// the input method tells us what's being composed (the preedit), which is
+// shown in the query strip, and then the text that's been decided on, which
+// is added to the query. we tell it where the query strip is, so that it can
+// put its candidate window next to it.
+
+use super::Window;
+use crate::render;
+
+use smithay_client_toolkit::reexports::protocols::wp::text_input::zv3::client::{
+    zwp_text_input_manager_v3::{self, ZwpTextInputManagerV3},
+    zwp_text_input_v3::{self, ContentHint, ContentPurpose, ZwpTextInputV3},
+};
+use smithay_client_toolkit::shell::WaylandSurface;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+
+pub struct TextInput {
+    input: ZwpTextInputV3,
+    // is the input method working on our surface?
+    enabled: bool,
+    // what the input method has sent since it was last done
+    pending_preedit: String,
+    pending_commit: Option<String>,
+    // the text that's being composed
+    pub preedit: String,
+}
+
+impl TextInput {
+    pub fn new(input: ZwpTextInputV3) -> TextInput {
+        TextInput {
+            input,
+            enabled: false,
+            pending_preedit: String::new(),
+            pending_commit: None,
+            preedit: String::new(),
+        }
+    }
+
+    pub fn destroy(self) {
+        self.input.destroy();
+    }
+}
+
+impl Window {
+    // tell the input method where the query strip is, e.g. after a resize.
+    pub(super) fn update_text_input(&self) {
+        let Some(text_input) = self.context.text_input.as_ref().filter(|t| t.enabled) else {
+            return;
+        };
+        set_cursor_rectangle(&text_input.input, self);
+        text_input.input.commit();
+    }
+}
+
+// the cursor rectangle is in surface coordinates, so it's unscaled.
+fn set_cursor_rectangle(input: &ZwpTextInputV3, window: &Window) {
+    let layer_space = crate::layout::Space {
+        width: window.width as f32,
+        height: window.height as f32,
+    };
+    let (pos, space) = render::query_strip_rect(&window.config, &layer_space);
+    input.set_cursor_rectangle(
+        pos.x as i32,
+        pos.y as i32,
+        space.width as i32,
+        space.height as i32,
+    );
+}
+
+impl Dispatch<ZwpTextInputManagerV3, ()> for Window {
+    fn event(
+        _: &mut Self,
+        _: &ZwpTextInputManagerV3,
+        _: zwp_text_input_manager_v3::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwpTextInputV3, ()> for Window {
+    fn event(
+        window: &mut Self,
+        input: &ZwpTextInputV3,
+        event: zwp_text_input_v3::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            // our surface has keyboard focus
+            zwp_text_input_v3::Event::Enter { surface } => {
+                if &surface != window.layer.wl_surface() {
+                    return;
+                }
+                input.enable();
+                input.set_content_type(ContentHint::None, ContentPurpose::Normal);
+                set_cursor_rectangle(input, window);
+                input.commit();
+                if let Some(text_input) = &mut window.context.text_input {
+                    text_input.enabled = true;
+                }
+            }
+            zwp_text_input_v3::Event::Leave { .. } => {
+                input.disable();
+                input.commit();
+                if let Some(text_input) = &mut window.context.text_input {
+                    text_input.enabled = false;
+                    if !std::mem::take(&mut text_input.preedit).is_empty() {
+                        window.state.needs_redraw = true;
+                        if window.configured {
+                            window.draw();
+                        }
+                    }
+                }
+            }
+            zwp_text_input_v3::Event::PreeditString { text, .. } => {
+                if let Some(text_input) = &mut window.context.text_input {
+                    text_input.pending_preedit = text.unwrap_or_default();
+                }
+            }
+            zwp_text_input_v3::Event::CommitString { text } => {
+                if let Some(text_input) = &mut window.context.text_input {
+                    text_input.pending_commit = text;
+                }
+            }
+            // everything that's been sent since the last done event takes effect
+            zwp_text_input_v3::Event::Done { .. } => {
+                let Some(text_input) = &mut window.context.text_input else {
+                    return;
+                };
+                let preedit = std::mem::take(&mut text_input.pending_preedit);
+                let commit = text_input.pending_commit.take();
+                let preedit_changed = preedit != text_input.preedit;
+                text_input.preedit = preedit;
+
+                if let Some(text) = commit.filter(|t| !t.is_empty()) {
+                    window.state.type_text(&text);
+                } else if preedit_changed {
+                    window.state.needs_redraw = true;
+                } else {
+                    return;
+                }
+                if window.configured && !window.state.should_exit {
+                    window.draw();
+                }
+            }
+            // we don't tell the input method about any surrounding text, so
+            // there's none for it to delete
+            _ => {}
+        }
+    }
+}